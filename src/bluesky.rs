@@ -1,112 +1,987 @@
-use atrium_api::agent::store::MemorySessionStore;
+mod client;
+mod identity;
+mod repo;
+mod session;
+
+use std::collections::HashMap;
+
+use async_trait::async_trait;
 use atrium_api::agent::AtpAgent;
+use atrium_api::app::bsky::embed;
+use atrium_api::app::bsky::embed::record_with_media::MainMediaRefs;
 use atrium_api::app::bsky::feed;
-use atrium_api::app::bsky::feed::defs::{FeedViewPostReasonRefs, ReplyRefParentRefs};
-use atrium_api::types::string::{AtIdentifier, Handle};
-use atrium_api::types::{LimitedNonZeroU8, TryFromUnknown, Union};
-use atrium_xrpc_client::reqwest::{ReqwestClient, ReqwestClientBuilder};
+use atrium_api::app::bsky::feed::defs::{
+    FeedViewPost, FeedViewPostReasonRefs, PostView, ReasonRepost, ReplyRef, ReplyRefParentRefs,
+    ThreadViewPost, ThreadViewPostRepliesItem,
+};
+use atrium_api::app::bsky::feed::get_post_thread::OutputThreadRefs;
+use atrium_api::app::bsky::feed::post::{RecordEmbedRefs, RecordLabelsRefs};
+use atrium_api::app::bsky::feed::{get_actor_likes, get_author_feed, get_post_thread};
+use atrium_api::app::bsky::richtext;
+use atrium_api::app::bsky::richtext::facet::MainFeaturesItem;
+use atrium_api::com::atproto::repo::strong_ref;
+use atrium_api::com::atproto::sync::get_repo;
+use atrium_api::did_doc::DidDocument;
+use atrium_api::types::string::{AtIdentifier, Did};
+use atrium_api::types::{
+    BlobRef, LimitedNonZeroU8, LimitedU16, TryFromUnknown, TypedBlobRef, Union,
+};
+use chrono::{DateTime, Utc};
+use tokio::sync::OnceCell;
+
+use crate::media::{extension_of_mime_type, MediaStore};
+use crate::models::{
+    tid_timestamp, BlueskyEmbed, BlueskyFacet, BlueskyFacetFeature, BlueskyLike, BlueskyMedia,
+    BlueskyParentState, BlueskyPost, BlueskyPostReply, BlueskyRecordRef, BlueskyRepost,
+    BlueskyThread, BlueskyThreadPost, MediaType,
+};
+use crate::retry::RetryPolicy;
+use crate::source::{Page, Source};
+use crate::storage::Storage;
+use crate::PluckError;
 
-use crate::{BlueskyPost, BlueskyPostReply};
+use client::BlueskyHttpClient;
+pub(crate) use client::RetryAfterResponse;
+pub use identity::*;
+pub use repo::*;
+pub use session::*;
 
-pub(crate) struct FetchPostsOutput {
+pub struct FetchPostsOutput {
     pub posts: Vec<BlueskyPost>,
     pub cursor: Option<String>,
+
+    /// The timestamp of the newest entry in the page of the feed, including any reposts.
+    pub newest_timestamp: Option<DateTime<Utc>>,
 }
 
-pub(crate) struct BlueskyFetcher {
-    client: AtpAgent<MemorySessionStore, ReqwestClient>,
+pub struct FetchRepostsOutput {
+    pub reposts: Vec<BlueskyRepost>,
+    pub cursor: Option<String>,
+
+    /// The timestamp of the newest entry in the page of the feed, including any posts.
+    pub newest_timestamp: Option<DateTime<Utc>>,
+}
+
+pub struct FetchThreadsOutput {
+    pub threads: Vec<BlueskyThread>,
+    pub cursor: Option<String>,
+
+    /// The timestamp of the newest entry in the page of the feed, including
+    /// any posts that didn't start a thread.
+    pub newest_timestamp: Option<DateTime<Utc>>,
+}
+
+pub struct FetchLikesOutput {
+    pub likes: Vec<BlueskyLike>,
+    pub cursor: Option<String>,
+}
+
+/// The AppView that serves public data without logging in.
+pub const PUBLIC_APPVIEW: &str = "https://public.api.bsky.app";
+
+pub struct BlueskyFetcher {
+    client: AtpAgent<BlueskySessionStore, BlueskyHttpClient>,
+    session_store: BlueskySessionStore,
+
+    /// The handle of the account to archive, or its DID when not logging in.
     handle: String,
-    app_password: String,
+
+    /// The app password to log in with, or `None` to use the public AppView.
+    app_password: Option<String>,
+
+    /// The account's DID and PDS, once they've been resolved.
+    identity: OnceCell<ResolvedIdentity>,
+
+    /// The PDS to log in to, or `None` to resolve it from the handle.
+    pds: Option<String>,
+
+    retry_policy: RetryPolicy,
+    media_store: Option<MediaStore>,
+}
+
+fn agent(
+    endpoint: &str,
+    store: BlueskySessionStore,
+) -> AtpAgent<BlueskySessionStore, BlueskyHttpClient> {
+    AtpAgent::new(BlueskyHttpClient::new(endpoint), store)
 }
 
 impl BlueskyFetcher {
     pub fn new(handle: String, app_password: String) -> Self {
+        Self::with_endpoint(DEFAULT_PDS, handle, Some(app_password))
+    }
+
+    /// Creates a fetcher that archives the given handle (or DID) without
+    /// logging in, using the public AppView.
+    ///
+    /// Posts, reposts, threads and the repository can be archived this way, but
+    /// likes can't, as they're only visible to the account itself.
+    pub fn public(actor: String) -> Self {
+        Self::with_endpoint(PUBLIC_APPVIEW, actor, None)
+    }
+
+    fn with_endpoint(endpoint: &str, handle: String, app_password: Option<String>) -> Self {
+        let session_store = BlueskySessionStore::in_memory();
+
         Self {
-            client: AtpAgent::new(
-                ReqwestClientBuilder::new("https://bsky.social")
-                    .client(reqwest::Client::default())
-                    .build(),
-                MemorySessionStore::default(),
-            ),
+            client: agent(endpoint, session_store.clone()),
+            session_store,
             handle,
             app_password,
+            identity: OnceCell::new(),
+            pds: None,
+            retry_policy: RetryPolicy::default(),
+            media_store: None,
         }
     }
 
-    pub async fn fetch_posts(
-        &mut self,
-        cursor: Option<String>,
-    ) -> Result<FetchPostsOutput, Box<dyn std::error::Error>> {
-        if self.client.get_session().await.is_none() {
-            self.client.login(&self.handle, &self.app_password).await?;
+    /// Returns whether the fetcher logs in, rather than using the public AppView.
+    pub fn is_authenticated(&self) -> bool {
+        self.app_password.is_some()
+    }
+
+    pub fn with_retry_policy(&mut self, retry_policy: RetryPolicy) -> &mut Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Keeps the session in the given store, such as one that saves it for later runs.
+    pub fn with_session_store(&mut self, session_store: BlueskySessionStore) -> &mut Self {
+        let endpoint = if self.is_authenticated() {
+            self.pds.as_deref().unwrap_or(DEFAULT_PDS)
+        } else {
+            PUBLIC_APPVIEW
+        };
+
+        self.client = agent(endpoint, session_store.clone());
+        self.session_store = session_store;
+        self
+    }
+
+    /// Logs in to the given PDS, rather than the one the handle resolves to.
+    ///
+    /// Without a login, the PDS is only used to download the repository and media.
+    pub fn with_pds(&mut self, pds: String) -> &mut Self {
+        if self.is_authenticated() {
+            self.client.configure_endpoint(pds.clone());
         }
 
-        use atrium_api::app::bsky::feed::get_author_feed::{self};
+        self.pds = Some(pds);
+        self
+    }
 
-        let response = self
-            .client
-            .api
-            .app
-            .bsky
-            .feed
-            .get_author_feed(
-                get_author_feed::ParametersData {
-                    actor: AtIdentifier::Handle(Handle::new(self.handle.clone()).unwrap()),
-                    cursor,
-                    limit: Some(LimitedNonZeroU8::<100>::MAX),
-                    filter: None,
+    /// Downloads the images and videos attached to each post into the given store.
+    pub fn with_media_store(&mut self, media_store: MediaStore) -> &mut Self {
+        self.media_store = Some(media_store);
+        self
+    }
+
+    /// Resumes the saved session, if there is one, or logs in otherwise.
+    ///
+    /// Expired access tokens are refreshed by the agent as they're used, and
+    /// the refreshed session is handed back to the session store.
+    async fn ensure_session(&self) -> Result<(), PluckError> {
+        let Some(app_password) = &self.app_password else {
+            return Ok(());
+        };
+
+        if self.client.get_session().await.is_some() {
+            return Ok(());
+        }
+
+        if let Some(session) = self.session_store.saved_session().await {
+            if session.handle.as_str() == self.handle {
+                if self.pds.is_none() {
+                    if let Some(pds) = session.did_doc.clone().and_then(|did_doc| {
+                        DidDocument::try_from_unknown(did_doc)
+                            .ok()
+                            .and_then(|did_doc| pds_endpoint(&did_doc))
+                    }) {
+                        self.client.configure_endpoint(pds);
+                    }
                 }
-                .into(),
-            )
+
+                match self.client.resume_session(session).await {
+                    Ok(()) => return Ok(()),
+                    Err(err) => eprintln!(
+                        "Failed to resume the saved Bluesky session ({}), logging in again",
+                        err
+                    ),
+                }
+            }
+        }
+
+        if self.pds.is_none() {
+            let identity = self.identity().await?;
+            self.client.configure_endpoint(identity.pds.clone());
+        }
+
+        self.retry_policy
+            .retry(|| async { Ok(self.client.login(&self.handle, app_password).await?) })
             .await?;
 
+        Ok(())
+    }
+
+    /// Resolves the account's DID and PDS, honoring the PDS given to [`Self::with_pds`].
+    async fn identity(&self) -> Result<&ResolvedIdentity, PluckError> {
+        self.identity
+            .get_or_try_init(|| async {
+                let mut resolver = IdentityResolver::new();
+                resolver.with_retry_policy(self.retry_policy.clone());
+
+                let identity = resolver.resolve(&self.handle).await?;
+
+                Ok(ResolvedIdentity {
+                    pds: self.pds.clone().unwrap_or(identity.pds),
+                    ..identity
+                })
+            })
+            .await
+    }
+
+    /// Returns the URL of the PDS that hosts the account's repository and media.
+    async fn pds(&self) -> Result<String, PluckError> {
+        if self.is_authenticated() {
+            self.ensure_session().await?;
+
+            return Ok(self.client.get_endpoint().await);
+        }
+
+        Ok(self.identity().await?.pds.clone())
+    }
+
+    fn actor(&self) -> Result<AtIdentifier, PluckError> {
+        self.handle
+            .parse()
+            .map_err(|_| PluckError::Bluesky(format!("invalid handle or DID: {}", self.handle)))
+    }
+
+    /// Fetches a page of the author's feed, which holds both their posts and their reposts.
+    async fn fetch_author_feed(
+        &self,
+        cursor: Option<String>,
+    ) -> Result<get_author_feed::Output, PluckError> {
+        self.ensure_session().await?;
+
+        self.retry_policy
+            .retry(|| async {
+                Ok(self
+                    .client
+                    .api
+                    .app
+                    .bsky
+                    .feed
+                    .get_author_feed(
+                        get_author_feed::ParametersData {
+                            actor: self.actor()?,
+                            cursor: cursor.clone(),
+                            limit: Some(LimitedNonZeroU8::<100>::MAX),
+                            filter: None,
+                        }
+                        .into(),
+                    )
+                    .await?)
+            })
+            .await
+    }
+
+    /// Downloads the images and videos in the given embed into the media
+    /// store, if there is one.
+    ///
+    /// Failed downloads are logged and skipped, so that one missing image
+    /// doesn't hold up the rest of the archive.
+    async fn download_embed_media(&self, did: &str, embed: &mut BlueskyEmbed) {
+        let Some(media_store) = &self.media_store else {
+            return;
+        };
+
+        let endpoint = match self.pds().await {
+            Ok(endpoint) => endpoint,
+            Err(err) => {
+                eprintln!("Failed to find the PDS to download media from: {}", err);
+                return;
+            }
+        };
+
+        for media in embed.media_mut() {
+            let url = format!(
+                "{}/xrpc/com.atproto.sync.getBlob?did={}&cid={}",
+                endpoint.trim_end_matches('/'),
+                did,
+                media.cid
+            );
+
+            match media_store
+                .download(&url, extension_of_mime_type(&media.mime_type))
+                .await
+            {
+                Ok(stored_media) => media.set_stored_media(stored_media),
+                Err(err) => eprintln!("Failed to download {}: {}", url, err),
+            }
+        }
+    }
+
+    pub async fn fetch_posts(
+        &mut self,
+        cursor: Option<String>,
+    ) -> Result<FetchPostsOutput, PluckError> {
+        let response = self.fetch_author_feed(cursor).await?;
+
         let cursor = response.cursor.clone();
 
         let mut posts = Vec::new();
 
         for feed_view_post in &response.feed {
-            let is_repost = if let Some(reason) = &feed_view_post.reason {
-                match reason {
-                    Union::Refs(FeedViewPostReasonRefs::ReasonRepost(_)) => true,
-                    Union::Unknown(_) => false,
-                }
-            } else {
-                false
-            };
+            if repost_reason(feed_view_post).is_some() {
+                continue;
+            }
+
+            let post = &feed_view_post.post;
+
+            let mut bluesky_post = post_of_view(post)?;
+
+            if let (Some(in_reply_to), Some(reply)) =
+                (&mut bluesky_post.in_reply_to, &feed_view_post.reply)
+            {
+                describe_parent(in_reply_to, reply);
+            }
 
-            if is_repost {
+            if let Some(embed) = &mut bluesky_post.embed {
+                self.download_embed_media(post.author.did.as_str(), embed)
+                    .await;
+            }
+
+            posts.push(bluesky_post);
+        }
+
+        Ok(FetchPostsOutput {
+            posts,
+            cursor,
+            newest_timestamp: newest_feed_timestamp(&response.feed),
+        })
+    }
+
+    /// Fetches the threads started by the posts in a page of the author's feed,
+    /// skipping posts that nobody has replied to, and archived threads whose
+    /// root post has the same number of replies as it had then.
+    pub async fn fetch_threads(
+        &mut self,
+        cursor: Option<String>,
+        archived_reply_counts: &HashMap<String, i64>,
+    ) -> Result<FetchThreadsOutput, PluckError> {
+        let response = self.fetch_author_feed(cursor).await?;
+
+        let cursor = response.cursor.clone();
+
+        let mut threads = Vec::new();
+
+        for feed_view_post in &response.feed {
+            let post = &feed_view_post.post;
+
+            if repost_reason(feed_view_post).is_some()
+                || feed_view_post.reply.is_some()
+                || post.reply_count.unwrap_or(0) == 0
+                || archived_reply_counts.get(&post.uri) == post.reply_count.as_ref()
+            {
                 continue;
             }
 
-            let in_reply_to = feed_view_post.reply.as_ref().and_then(|reply| {
-                let parent = match &reply.parent {
-                    Union::Refs(parent) => parent,
-                    Union::Unknown(_) => return None,
-                };
-
-                match parent {
-                    ReplyRefParentRefs::PostView(parent) => Some(BlueskyPostReply {
-                        uri: parent.uri.clone(),
-                        author_did: parent.author.did.to_string(),
-                        author_handle: parent.author.handle.to_string(),
-                    }),
-                    ReplyRefParentRefs::NotFoundPost(_) | ReplyRefParentRefs::BlockedPost(_) => {
-                        None
-                    }
-                }
+            let root = post_of_view(post)?;
+
+            let output = self
+                .retry_policy
+                .retry(|| async {
+                    Ok(self
+                        .client
+                        .api
+                        .app
+                        .bsky
+                        .feed
+                        .get_post_thread(
+                            get_post_thread::ParametersData {
+                                uri: root.uri.clone(),
+                                depth: Some(LimitedU16::MAX),
+                                parent_height: Some(LimitedU16::MIN),
+                            }
+                            .into(),
+                        )
+                        .await?)
+                })
+                .await?;
+
+            let mut replies = Vec::new();
+
+            if let Union::Refs(OutputThreadRefs::AppBskyFeedDefsThreadViewPost(thread)) =
+                &output.thread
+            {
+                collect_replies(thread, &mut replies)?;
+            }
+
+            threads.push(BlueskyThread {
+                uri: root.uri,
+                created_at: root.created_at,
+                reply_count: root.reply_count,
+                replies,
+            });
+        }
+
+        Ok(FetchThreadsOutput {
+            threads,
+            cursor,
+            newest_timestamp: newest_feed_timestamp(&response.feed),
+        })
+    }
+
+    pub async fn fetch_reposts(
+        &mut self,
+        cursor: Option<String>,
+    ) -> Result<FetchRepostsOutput, PluckError> {
+        let response = self.fetch_author_feed(cursor).await?;
+
+        let cursor = response.cursor.clone();
+
+        let mut reposts = Vec::new();
+
+        for feed_view_post in &response.feed {
+            let Some(reason) = repost_reason(feed_view_post) else {
+                continue;
+            };
+
+            let post = &feed_view_post.post;
+            let record = decode_post_record(post)?;
+
+            reposts.push(BlueskyRepost {
+                reposted_at: reason.indexed_at.as_ref().to_utc(),
+                post_uri: post.uri.clone(),
+                post_cid: post.cid.as_ref().to_string(),
+                author_did: post.author.did.to_string(),
+                author_handle: Some(post.author.handle.to_string()),
+                text: Some(record.text),
+                created_at: Some(record.created_at.as_ref().to_utc()),
             });
+        }
 
+        Ok(FetchRepostsOutput {
+            reposts,
+            cursor,
+            newest_timestamp: newest_feed_timestamp(&response.feed),
+        })
+    }
+
+    /// Downloads the account's whole repository as a CAR file, which can be
+    /// read with [`BlueskyRepo::from_car`].
+    ///
+    /// Repositories are public, so they're downloaded straight from the PDS
+    /// whether or not the fetcher logs in.
+    pub async fn fetch_repo(&self) -> Result<Vec<u8>, PluckError> {
+        let pds = self.pds().await?;
+
+        let did = match self.client.get_session().await {
+            Some(session) => session.data.did,
+            None => Did::new(self.identity().await?.did.clone())
+                .map_err(|err| PluckError::Bluesky(err.to_string()))?,
+        };
+
+        let client = agent(&pds, BlueskySessionStore::in_memory());
+
+        self.retry_policy
+            .retry(|| async {
+                Ok(client
+                    .api
+                    .com
+                    .atproto
+                    .sync
+                    .get_repo(
+                        get_repo::ParametersData {
+                            did: did.clone(),
+                            since: None,
+                        }
+                        .into(),
+                    )
+                    .await?)
+            })
+            .await
+    }
+
+    pub async fn fetch_likes(
+        &mut self,
+        cursor: Option<String>,
+    ) -> Result<FetchLikesOutput, PluckError> {
+        if !self.is_authenticated() {
+            return Err(PluckError::Bluesky(
+                "likes can only be archived when logged in".to_string(),
+            ));
+        }
+
+        self.ensure_session().await?;
+
+        let response = self
+            .retry_policy
+            .retry(|| async {
+                Ok(self
+                    .client
+                    .api
+                    .app
+                    .bsky
+                    .feed
+                    .get_actor_likes(
+                        get_actor_likes::ParametersData {
+                            actor: self.actor()?,
+                            cursor: cursor.clone(),
+                            limit: Some(LimitedNonZeroU8::<100>::MAX),
+                        }
+                        .into(),
+                    )
+                    .await?)
+            })
+            .await?;
+
+        let cursor = response.cursor.clone();
+
+        let mut likes = Vec::new();
+
+        for feed_view_post in &response.feed {
             let post = &feed_view_post.post;
-            let record = feed::post::RecordData::try_from_unknown(post.record.clone())?;
+            let record = decode_post_record(post)?;
+
+            let uri = post.viewer.as_ref().and_then(|viewer| viewer.like.clone());
+
+            let liked_at = uri
+                .as_deref()
+                .and_then(|uri| uri.rsplit('/').next())
+                .and_then(tid_timestamp)
+                .unwrap_or_else(|| post.indexed_at.as_ref().to_utc());
 
-            posts.push(BlueskyPost {
-                uri: post.uri.clone(),
-                text: record.text,
-                created_at: record.created_at.as_ref().to_utc(),
-                in_reply_to,
+            likes.push(BlueskyLike {
+                uri,
+                liked_at,
+                post_uri: post.uri.clone(),
+                post_cid: post.cid.as_ref().to_string(),
+                author_did: post.author.did.to_string(),
+                author_handle: Some(post.author.handle.to_string()),
+                text: Some(record.text),
+                created_at: Some(record.created_at.as_ref().to_utc()),
             });
         }
 
-        Ok(FetchPostsOutput { posts, cursor })
+        Ok(FetchLikesOutput { likes, cursor })
+    }
+}
+
+/// Returns why the given item is in the author's feed, if it is there because
+/// they reposted it.
+fn repost_reason(feed_view_post: &FeedViewPost) -> Option<&ReasonRepost> {
+    match &feed_view_post.reason {
+        Some(Union::Refs(FeedViewPostReasonRefs::ReasonRepost(reason))) => Some(reason),
+        Some(Union::Unknown(_)) | None => None,
+    }
+}
+
+/// Returns the time of the newest entry in a page of an author feed: when it
+/// was reposted for reposts, and when it was indexed for posts.
+///
+/// Each source skips some of the entries, so this is what tells a sync whether
+/// it has reached entries it has already archived, even when a page holds
+/// nothing the source wants.
+fn newest_feed_timestamp(feed: &[FeedViewPost]) -> Option<DateTime<Utc>> {
+    feed.iter()
+        .map(|feed_view_post| match repost_reason(feed_view_post) {
+            Some(reason) => reason.indexed_at.as_ref().to_utc(),
+            None => feed_view_post.post.indexed_at.as_ref().to_utc(),
+        })
+        .max()
+}
+
+/// Converts a post fetched from the AppView into a [`BlueskyPost`].
+fn post_of_view(post: &PostView) -> Result<BlueskyPost, PluckError> {
+    let record = decode_post_record(post)?;
+
+    Ok(BlueskyPost {
+        cid: Some(post.cid.as_ref().to_string()),
+        indexed_at: Some(post.indexed_at.as_ref().to_utc()),
+        like_count: post.like_count,
+        repost_count: post.repost_count,
+        reply_count: post.reply_count,
+        quote_count: post.quote_count,
+        ..post_of_record(post.uri.clone(), record)
+    })
+}
+
+/// Fills in the details of a reply's parent that only the AppView knows, like
+/// its text and whether it can still be seen.
+fn describe_parent(in_reply_to: &mut BlueskyPostReply, reply: &ReplyRef) {
+    let Union::Refs(parent) = &reply.parent else {
+        return;
+    };
+
+    match parent {
+        ReplyRefParentRefs::PostView(parent) => {
+            in_reply_to.author_did = parent.author.did.to_string();
+            in_reply_to.author_handle = Some(parent.author.handle.to_string());
+            in_reply_to.text = decode_post_record(parent).ok().map(|record| record.text);
+            in_reply_to.state = Some(BlueskyParentState::Visible);
+        }
+        ReplyRefParentRefs::NotFoundPost(_) => {
+            in_reply_to.state = Some(BlueskyParentState::NotFound);
+        }
+        ReplyRefParentRefs::BlockedPost(parent) => {
+            in_reply_to.author_did = parent.author.did.to_string();
+            in_reply_to.state = Some(BlueskyParentState::Blocked);
+        }
+    }
+}
+
+/// Collects every reply beneath the given post, depth first.
+///
+/// Replies that have been deleted or blocked are skipped, along with the
+/// replies to them, which the AppView doesn't return.
+fn collect_replies(
+    thread: &ThreadViewPost,
+    replies: &mut Vec<BlueskyThreadPost>,
+) -> Result<(), PluckError> {
+    for reply in thread.replies.iter().flatten() {
+        let Union::Refs(ThreadViewPostRepliesItem::ThreadViewPost(reply)) = reply else {
+            continue;
+        };
+
+        replies.push(BlueskyThreadPost {
+            author_did: reply.post.author.did.to_string(),
+            author_handle: reply.post.author.handle.to_string(),
+            post: post_of_view(&reply.post)?,
+        });
+
+        collect_replies(reply, replies)?;
+    }
+
+    Ok(())
+}
+
+/// Returns the DID in an `at://` URI, e.g. `did:plc:abc` in
+/// `at://did:plc:abc/app.bsky.feed.post/123`.
+fn did_of_uri(uri: &str) -> Option<&str> {
+    uri.strip_prefix("at://")
+        .and_then(|rest| rest.split('/').next())
+        .filter(|authority| authority.starts_with("did:"))
+}
+
+/// Converts a post record into a [`BlueskyPost`], without any of the details
+/// that only the AppView knows, like engagement counts.
+fn post_of_record(uri: String, record: feed::post::RecordData) -> BlueskyPost {
+    let in_reply_to = record.reply.as_ref().map(|reply| BlueskyPostReply {
+        uri: reply.parent.uri.clone(),
+        author_did: did_of_uri(&reply.parent.uri)
+            .unwrap_or_default()
+            .to_string(),
+        author_handle: None,
+        text: None,
+        state: None,
+        root: Some(record_ref(&reply.root)),
+    });
+
+    let embed = record.embed.as_ref().and_then(embed_of_record);
+
+    let facets = record
+        .facets
+        .as_ref()
+        .map(|facets| facets.iter().map(facet_of_record).collect());
+
+    let langs = record
+        .langs
+        .as_ref()
+        .map(|langs| langs.iter().map(|lang| lang.as_ref().to_string()).collect());
+
+    let labels = match &record.labels {
+        Some(Union::Refs(RecordLabelsRefs::ComAtprotoLabelDefsSelfLabels(labels))) => Some(
+            labels
+                .values
+                .iter()
+                .map(|label| label.val.clone())
+                .collect(),
+        ),
+        Some(Union::Unknown(_)) | None => None,
+    };
+
+    BlueskyPost {
+        uri,
+        created_at: record.created_at.as_ref().to_utc(),
+        text: record.text,
+        langs,
+        labels,
+        cid: None,
+        indexed_at: None,
+        like_count: None,
+        repost_count: None,
+        reply_count: None,
+        quote_count: None,
+        deleted_at: None,
+        in_reply_to,
+        facets,
+        embed,
+        revisions: None,
+    }
+}
+
+fn decode_post_record(post: &PostView) -> Result<feed::post::RecordData, PluckError> {
+    feed::post::RecordData::try_from_unknown(post.record.clone())
+        .map_err(|err| PluckError::Bluesky(err.to_string()))
+}
+
+fn facet_of_record(facet: &richtext::facet::Main) -> BlueskyFacet {
+    BlueskyFacet {
+        byte_start: facet.index.byte_start,
+        byte_end: facet.index.byte_end,
+        features: facet
+            .features
+            .iter()
+            .filter_map(|feature| match feature {
+                Union::Refs(MainFeaturesItem::Link(link)) => Some(BlueskyFacetFeature::Link {
+                    uri: link.uri.clone(),
+                }),
+                Union::Refs(MainFeaturesItem::Mention(mention)) => {
+                    Some(BlueskyFacetFeature::Mention {
+                        did: mention.did.to_string(),
+                    })
+                }
+                Union::Refs(MainFeaturesItem::Tag(tag)) => Some(BlueskyFacetFeature::Tag {
+                    tag: tag.tag.clone(),
+                }),
+                Union::Unknown(_) => None,
+            })
+            .collect(),
+    }
+}
+
+/// Returns the embed in a post, or `None` if it is of a kind we don't know about.
+fn embed_of_record(embed: &Union<RecordEmbedRefs>) -> Option<BlueskyEmbed> {
+    let Union::Refs(embed) = embed else {
+        return None;
+    };
+
+    Some(match embed {
+        RecordEmbedRefs::AppBskyEmbedImagesMain(images) => images_embed(images),
+        RecordEmbedRefs::AppBskyEmbedVideoMain(video) => video_embed(video),
+        RecordEmbedRefs::AppBskyEmbedExternalMain(external) => external_embed(external),
+        RecordEmbedRefs::AppBskyEmbedRecordMain(record) => BlueskyEmbed::Record {
+            record: record_ref(&record.record),
+        },
+        RecordEmbedRefs::AppBskyEmbedRecordWithMediaMain(record_with_media) => {
+            let Union::Refs(media) = &record_with_media.media else {
+                return None;
+            };
+
+            BlueskyEmbed::RecordWithMedia {
+                record: record_ref(&record_with_media.record.record),
+                media: Box::new(match media {
+                    MainMediaRefs::AppBskyEmbedImagesMain(images) => images_embed(images),
+                    MainMediaRefs::AppBskyEmbedVideoMain(video) => video_embed(video),
+                    MainMediaRefs::AppBskyEmbedExternalMain(external) => external_embed(external),
+                }),
+            }
+        }
+    })
+}
+
+fn images_embed(images: &embed::images::Main) -> BlueskyEmbed {
+    BlueskyEmbed::Images {
+        images: images
+            .images
+            .iter()
+            .map(|image| {
+                blob_media(
+                    MediaType::Photo,
+                    &image.image,
+                    Some(image.alt.clone()).filter(|alt| !alt.is_empty()),
+                )
+            })
+            .collect(),
+    }
+}
+
+fn video_embed(video: &embed::video::Main) -> BlueskyEmbed {
+    BlueskyEmbed::Video {
+        video: blob_media(MediaType::Video, &video.video, video.alt.clone()),
+    }
+}
+
+fn external_embed(external: &embed::external::Main) -> BlueskyEmbed {
+    let external = &external.external;
+
+    BlueskyEmbed::External {
+        uri: external.uri.clone(),
+        title: external.title.clone(),
+        description: external.description.clone(),
+        thumb: external
+            .thumb
+            .as_ref()
+            .map(|thumb| blob_media(MediaType::Photo, thumb, None)),
+    }
+}
+
+fn record_ref(strong_ref: &strong_ref::Main) -> BlueskyRecordRef {
+    BlueskyRecordRef {
+        uri: strong_ref.uri.clone(),
+        cid: strong_ref.cid.as_ref().to_string(),
+    }
+}
+
+fn blob_media(r#type: MediaType, blob_ref: &BlobRef, alt: Option<String>) -> BlueskyMedia {
+    let (cid, mime_type) = match blob_ref {
+        BlobRef::Typed(TypedBlobRef::Blob(blob)) => {
+            (blob.r#ref.0.to_string(), blob.mime_type.clone())
+        }
+        BlobRef::Untyped(blob) => (blob.cid.clone(), blob.mime_type.clone()),
+    };
+
+    BlueskyMedia {
+        r#type,
+        cid,
+        mime_type,
+        alt,
+        local_path: None,
+        sha256: None,
+    }
+}
+
+#[async_trait]
+impl Source for BlueskyFetcher {
+    type Item = BlueskyPost;
+    type Cursor = String;
+
+    // The author feed holds all of the account's posts (and replies), so a post
+    // that's missing from it has been deleted.
+    fn tracks_deletions(&self) -> bool {
+        true
+    }
+
+    async fn fetch_page(
+        &mut self,
+        cursor: Option<String>,
+    ) -> Result<Page<BlueskyPost, String>, PluckError> {
+        let FetchPostsOutput {
+            posts,
+            cursor,
+            newest_timestamp,
+        } = self.fetch_posts(cursor).await?;
+
+        Ok(Page {
+            items: posts,
+            next_cursor: cursor,
+            newest_timestamp,
+        })
+    }
+}
+
+/// The reposts in a [`BlueskyFetcher`]'s author feed, as a [`Source`].
+pub struct BlueskyReposts<'a>(pub &'a mut BlueskyFetcher);
+
+#[async_trait]
+impl Source for BlueskyReposts<'_> {
+    type Item = BlueskyRepost;
+    type Cursor = String;
+
+    async fn fetch_page(
+        &mut self,
+        cursor: Option<String>,
+    ) -> Result<Page<BlueskyRepost, String>, PluckError> {
+        let FetchRepostsOutput {
+            reposts,
+            cursor,
+            newest_timestamp,
+        } = self.0.fetch_reposts(cursor).await?;
+
+        Ok(Page {
+            items: reposts,
+            next_cursor: cursor,
+            newest_timestamp,
+        })
+    }
+}
+
+/// The threads started by a [`BlueskyFetcher`]'s account, as a [`Source`].
+pub struct BlueskyThreads<'a> {
+    fetcher: &'a mut BlueskyFetcher,
+
+    /// The reply counts of the threads that don't need fetching again, by the
+    /// URI of their root post.
+    archived_reply_counts: HashMap<String, i64>,
+}
+
+impl<'a> BlueskyThreads<'a> {
+    pub fn new(fetcher: &'a mut BlueskyFetcher) -> Self {
+        Self {
+            fetcher,
+            archived_reply_counts: HashMap::new(),
+        }
+    }
+
+    /// Skips the threads archived in the given storage, unless their root post
+    /// has been replied to (or had a reply deleted) since.
+    ///
+    /// Replies further down a thread don't change its root post's reply count,
+    /// so these are only picked up once the thread is fetched again.
+    pub async fn skip_unchanged(&mut self, storage: &impl Storage) -> Result<(), PluckError> {
+        for partition in storage.partitions::<BlueskyThread>().await? {
+            let threads = storage
+                .read_partition::<BlueskyThread>(partition)
+                .await?
+                .unwrap_or_default();
+
+            for thread in threads {
+                if let Some(reply_count) = thread.reply_count {
+                    self.archived_reply_counts.insert(thread.uri, reply_count);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Source for BlueskyThreads<'_> {
+    type Item = BlueskyThread;
+    type Cursor = String;
+
+    async fn fetch_page(
+        &mut self,
+        cursor: Option<String>,
+    ) -> Result<Page<BlueskyThread, String>, PluckError> {
+        let FetchThreadsOutput {
+            threads,
+            cursor,
+            newest_timestamp,
+        } = self
+            .fetcher
+            .fetch_threads(cursor, &self.archived_reply_counts)
+            .await?;
+
+        Ok(Page {
+            items: threads,
+            next_cursor: cursor,
+            newest_timestamp,
+        })
+    }
+}
+
+/// The posts liked by a [`BlueskyFetcher`]'s account, as a [`Source`].
+pub struct BlueskyLikes<'a>(pub &'a mut BlueskyFetcher);
+
+#[async_trait]
+impl Source for BlueskyLikes<'_> {
+    type Item = BlueskyLike;
+    type Cursor = String;
+
+    async fn fetch_page(
+        &mut self,
+        cursor: Option<String>,
+    ) -> Result<Page<BlueskyLike, String>, PluckError> {
+        let FetchLikesOutput { likes, cursor } = self.0.fetch_likes(cursor).await?;
+
+        Ok(Page {
+            items: likes,
+            next_cursor: cursor,
+            newest_timestamp: None,
+        })
     }
 }
@@ -0,0 +1,132 @@
+use std::time::Duration;
+
+use atrium_api::xrpc::error::ErrorResponseBody;
+use atrium_api::xrpc::http::header::RETRY_AFTER;
+use atrium_api::xrpc::http::{HeaderMap, Request, Response, StatusCode};
+use atrium_api::xrpc::{HttpClient, XrpcClient};
+use atrium_xrpc_client::reqwest::{ReqwestClient, ReqwestClientBuilder};
+use chrono::{DateTime, Utc};
+use thiserror::Error;
+
+use crate::retry::parse_retry_after;
+
+/// The HTTP client that the Bluesky agent sends its requests with.
+///
+/// atrium's XRPC errors only keep the status and body of an unsuccessful
+/// response, so responses that say when to retry are turned into
+/// [`RetryAfterResponse`] errors here, while their headers are still around.
+#[derive(Clone)]
+pub(crate) struct BlueskyHttpClient {
+    inner: ReqwestClient,
+}
+
+impl BlueskyHttpClient {
+    pub fn new(endpoint: &str) -> Self {
+        Self {
+            inner: ReqwestClientBuilder::new(endpoint)
+                .client(reqwest::Client::default())
+                .build(),
+        }
+    }
+}
+
+impl HttpClient for BlueskyHttpClient {
+    async fn send_http(
+        &self,
+        request: Request<Vec<u8>>,
+    ) -> Result<Response<Vec<u8>>, Box<dyn std::error::Error + Send + Sync + 'static>> {
+        let response = self.inner.send_http(request).await?;
+
+        if response.status().is_success() {
+            return Ok(response);
+        }
+
+        let headers = response.headers();
+
+        // Bluesky sends its rate limit headers with every response, so the
+        // time the limit resets only matters once it has been reached.
+        let retry_after = match response.status() {
+            StatusCode::TOO_MANY_REQUESTS => {
+                retry_after(headers).or_else(|| rate_limit_reset(headers))
+            }
+            status if status.is_server_error() => retry_after(headers),
+            _ => None,
+        };
+
+        let Some(retry_after) = retry_after else {
+            return Ok(response);
+        };
+
+        let message = serde_json::from_slice::<ErrorResponseBody>(response.body())
+            .map(|body| body.to_string())
+            .unwrap_or_default();
+
+        Err(Box::new(RetryAfterResponse {
+            status: response.status().as_u16(),
+            message,
+            retry_after,
+        }))
+    }
+}
+
+impl XrpcClient for BlueskyHttpClient {
+    fn base_uri(&self) -> String {
+        self.inner.base_uri()
+    }
+}
+
+/// An unsuccessful XRPC response that said how long to wait before retrying.
+#[derive(Debug, Error)]
+#[error("{status} {message}")]
+pub(crate) struct RetryAfterResponse {
+    pub status: u16,
+    pub message: String,
+    pub retry_after: Duration,
+}
+
+/// Returns how long the response asked us to wait, from its `Retry-After` header.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    headers
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_retry_after)
+}
+
+/// Returns how long until the rate limit resets, from the `ratelimit-reset`
+/// header (in seconds since the epoch).
+fn rate_limit_reset(headers: &HeaderMap) -> Option<Duration> {
+    let reset_at = headers.get("ratelimit-reset")?.to_str().ok()?;
+    let reset_at = DateTime::from_timestamp(reset_at.trim().parse().ok()?, 0)?;
+
+    (reset_at - Utc::now()).to_std().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use atrium_api::xrpc::http::HeaderValue;
+
+    use super::*;
+
+    #[test]
+    fn rate_limits_reset_at_the_given_time() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(rate_limit_reset(&headers), None);
+
+        let reset_at = Utc::now() + chrono::Duration::minutes(5);
+        headers.insert(
+            "ratelimit-reset",
+            HeaderValue::from_str(&reset_at.timestamp().to_string()).unwrap(),
+        );
+
+        let delay = rate_limit_reset(&headers).unwrap();
+        assert!(delay > Duration::from_secs(4 * 60) && delay <= Duration::from_secs(5 * 60));
+
+        let reset_at = Utc::now() - chrono::Duration::minutes(5);
+        headers.insert(
+            "ratelimit-reset",
+            HeaderValue::from_str(&reset_at.timestamp().to_string()).unwrap(),
+        );
+
+        assert_eq!(rate_limit_reset(&headers), None);
+    }
+}
@@ -0,0 +1,163 @@
+use atrium_api::did_doc::DidDocument;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::retry::{parse_retry_after, RetryPolicy};
+use crate::PluckError;
+
+/// The PDS that accounts are assumed to be on until we know better, and that
+/// handles are resolved with when they can't be resolved over HTTPS.
+pub const DEFAULT_PDS: &str = "https://bsky.social";
+
+/// The directory that `did:plc` DIDs are resolved with.
+const PLC_DIRECTORY: &str = "https://plc.directory";
+
+/// An account's DID, along with the PDS that hosts its repository.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedIdentity {
+    pub did: String,
+
+    /// The URL of the account's PDS, e.g. `https://morel.us-east.host.bsky.network`.
+    pub pds: String,
+}
+
+#[derive(Deserialize)]
+struct ResolveHandleOutput {
+    did: String,
+}
+
+/// Resolves handles to DIDs, and DIDs to the PDS that hosts them, so that
+/// accounts on self-hosted PDSes can be archived.
+///
+/// See <https://atproto.com/specs/handle#handle-resolution> and
+/// <https://atproto.com/specs/did#did-documents>.
+#[derive(Debug, Clone)]
+pub struct IdentityResolver {
+    client: reqwest::Client,
+    retry_policy: RetryPolicy,
+}
+
+impl IdentityResolver {
+    pub fn new() -> Self {
+        Self {
+            client: reqwest::Client::default(),
+            retry_policy: RetryPolicy::default(),
+        }
+    }
+
+    pub fn with_retry_policy(&mut self, retry_policy: RetryPolicy) -> &mut Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Resolves the given handle (or DID) to its DID and PDS.
+    pub async fn resolve(&self, identifier: &str) -> Result<ResolvedIdentity, PluckError> {
+        let did = if identifier.starts_with("did:") {
+            identifier.to_string()
+        } else {
+            self.resolve_handle(identifier).await?
+        };
+
+        let did_doc = self.resolve_did(&did).await?;
+
+        let pds = pds_endpoint(&did_doc)
+            .ok_or_else(|| PluckError::Bluesky(format!("{} does not list a PDS", did)))?;
+
+        Ok(ResolvedIdentity { did, pds })
+    }
+
+    /// Resolves a handle to a DID.
+    ///
+    /// Handles are resolved from the handle's own domain where possible, falling
+    /// back to the [`DEFAULT_PDS`] for handles that are only verified over DNS.
+    pub async fn resolve_handle(&self, handle: &str) -> Result<String, PluckError> {
+        let well_known_url = format!("https://{}/.well-known/atproto-did", handle);
+
+        // Most handles aren't resolvable this way, so it isn't worth retrying.
+        if let Ok(did) = self.request(&well_known_url).await {
+            let did = did.trim();
+
+            if did.starts_with("did:") {
+                return Ok(did.to_string());
+            }
+        }
+
+        let url = format!(
+            "{}/xrpc/com.atproto.identity.resolveHandle?handle={}",
+            DEFAULT_PDS, handle
+        );
+
+        let output: ResolveHandleOutput = self.get_json(&url).await?;
+
+        Ok(output.did)
+    }
+
+    /// Fetches the DID document for a `did:plc` or `did:web` DID.
+    pub async fn resolve_did(&self, did: &str) -> Result<DidDocument, PluckError> {
+        let url = if did.starts_with("did:plc:") {
+            format!("{}/{}", PLC_DIRECTORY, did)
+        } else if let Some(host) = did.strip_prefix("did:web:") {
+            format!("https://{}/.well-known/did.json", host.replace("%3A", ":"))
+        } else {
+            return Err(PluckError::Bluesky(format!(
+                "unsupported DID method: {}",
+                did
+            )));
+        };
+
+        self.get_json(&url).await
+    }
+
+    async fn get_json<T: DeserializeOwned>(&self, url: &str) -> Result<T, PluckError> {
+        let text = self.get_text(url).await?;
+
+        serde_json::from_str(&text).map_err(|err| PluckError::deserialize(url, err))
+    }
+
+    async fn get_text(&self, url: &str) -> Result<String, PluckError> {
+        self.retry_policy.retry(|| self.request(url)).await
+    }
+
+    async fn request(&self, url: &str) -> Result<String, PluckError> {
+        let response = self.client.get(url).send().await?;
+
+        let status = response.status();
+
+        if !status.is_success() {
+            let retry_after = response
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(parse_retry_after);
+
+            return Err(PluckError::HttpStatus {
+                url: url.to_string(),
+                status,
+                retry_after,
+            });
+        }
+
+        Ok(response.text().await?)
+    }
+}
+
+impl Default for IdentityResolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns the URL of the PDS listed in the given DID document.
+pub fn pds_endpoint(did_doc: &DidDocument) -> Option<String> {
+    let full_id = format!("{}#atproto_pds", did_doc.id);
+
+    did_doc
+        .service
+        .as_ref()?
+        .iter()
+        .find(|service| {
+            (service.id == "#atproto_pds" || service.id == full_id)
+                && service.r#type == "AtprotoPersonalDataServer"
+        })
+        .map(|service| service.service_endpoint.clone())
+}
@@ -0,0 +1,690 @@
+use std::collections::{HashMap, HashSet};
+use std::io::Cursor;
+use std::marker::PhantomData;
+use std::path::Path;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use atrium_api::app::bsky::{feed, graph};
+use atrium_api::com::atproto::repo::strong_ref;
+use base64::engine::general_purpose::STANDARD_NO_PAD;
+use base64::Engine;
+use ipld_core::cid::Cid;
+use ipld_core::ipld::Ipld;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use super::{did_of_uri, post_of_record};
+use crate::models::{BlueskyBlock, BlueskyFollow, BlueskyLike, BlueskyPost, BlueskyRepost, Record};
+use crate::source::{Page, Source};
+use crate::PluckError;
+
+/// The name of the file that a downloaded repository is kept in, within the
+/// output directory.
+pub const REPO_CAR_FILE_NAME: &str = "repo.car";
+
+/// The multihash code of SHA-256, which every block in a repository is hashed with.
+const SHA2_256: u64 = 0x12;
+
+/// How deep a Merkle Search Tree may be. Each layer holds roughly four times
+/// as many keys as the one below it, so real repositories are nowhere near this.
+const MAX_MST_DEPTH: usize = 64;
+
+/// A Bluesky repository, as exported by `com.atproto.sync.getRepo`.
+///
+/// Repositories are exported as [CAR files] holding a signed commit, which
+/// points at the root of a [Merkle Search Tree] of every record in the repo.
+///
+/// [CAR files]: https://ipld.io/specs/transport/car/carv1/
+/// [Merkle Search Tree]: https://atproto.com/specs/repository#repo-data-structure
+pub struct BlueskyRepo {
+    did: String,
+    entries: Vec<RepoEntry>,
+}
+
+/// A record in a [`BlueskyRepo`].
+struct RepoEntry {
+    /// The collection the record belongs to, e.g. `app.bsky.feed.post`.
+    collection: String,
+    rkey: String,
+    cid: Cid,
+
+    /// The DAG-CBOR encoded record.
+    data: Vec<u8>,
+}
+
+#[derive(Deserialize)]
+struct CarHeader {
+    version: u64,
+    roots: Vec<Cid>,
+}
+
+#[derive(Deserialize)]
+struct Commit {
+    did: String,
+
+    /// The root of the Merkle Search Tree.
+    data: Cid,
+}
+
+#[derive(Deserialize)]
+struct MstNode {
+    /// The subtree of keys that sort before the first entry.
+    l: Option<Cid>,
+    e: Vec<MstEntry>,
+}
+
+#[derive(Deserialize)]
+struct MstEntry {
+    /// How many bytes of the previous entry's key this entry's key starts with.
+    p: usize,
+
+    /// The rest of the key.
+    #[serde(with = "serde_bytes")]
+    k: Vec<u8>,
+
+    /// The record.
+    v: Cid,
+
+    /// The subtree of keys that sort between this entry and the next.
+    t: Option<Cid>,
+}
+
+impl BlueskyRepo {
+    /// Reads the repository in the CAR file at the given path.
+    pub fn open(path: &Path) -> Result<Self, PluckError> {
+        let car = std::fs::read(path).map_err(|err| PluckError::storage(path, err))?;
+
+        Self::from_car(&car)
+    }
+
+    /// Parses a repository from the contents of a CAR file.
+    pub fn from_car(car: &[u8]) -> Result<Self, PluckError> {
+        let (header, blocks) = read_car(car)?;
+
+        if header.version != 1 {
+            return Err(invalid_repo(format!(
+                "unsupported CAR version {}",
+                header.version
+            )));
+        }
+
+        let root = header
+            .roots
+            .first()
+            .ok_or_else(|| invalid_repo("CAR file has no root"))?;
+
+        let commit: Commit = decode_block(&blocks, root)?;
+
+        let mut entries = Vec::new();
+        let mut visited = HashSet::new();
+        walk_mst(&blocks, &commit.data, 0, &mut visited, &mut entries)?;
+
+        Ok(Self {
+            did: commit.did,
+            entries,
+        })
+    }
+
+    /// Returns the DID of the account the repository belongs to.
+    pub fn did(&self) -> &str {
+        &self.did
+    }
+
+    /// Returns the records of the given type in the repository.
+    ///
+    /// Records that can't be read are skipped with a warning, so that one
+    /// malformed record doesn't hold up the rest of the export.
+    pub fn records<T: RepoRecord>(&self) -> Vec<T> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.collection == T::NSID)
+            .filter_map(|entry| {
+                let uri = format!("at://{}/{}/{}", self.did, entry.collection, entry.rkey);
+
+                let result = serde_ipld_dagcbor::from_slice::<Ipld>(&entry.data)
+                    .map_err(|err| err.to_string())
+                    .and_then(|data| {
+                        serde_json::from_value(json_of_ipld(data)).map_err(|err| err.to_string())
+                    })
+                    .and_then(|data| T::from_repo(uri.clone(), entry.cid.to_string(), data));
+
+                match result {
+                    Ok(record) => Some(record),
+                    Err(err) => {
+                        eprintln!("Skipping {}: {}", uri, err);
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+/// Converts a record to its JSON representation, which is what the lexicon
+/// types expect.
+///
+/// See <https://atproto.com/specs/data-model#json-representation>.
+fn json_of_ipld(ipld: Ipld) -> Value {
+    match ipld {
+        Ipld::Null => Value::Null,
+        Ipld::Bool(bool) => Value::Bool(bool),
+        Ipld::Integer(integer) => match i64::try_from(integer) {
+            Ok(integer) => Value::from(integer),
+            Err(_) => Value::from(integer as f64),
+        },
+        Ipld::Float(float) => Value::from(float),
+        Ipld::String(string) => Value::String(string),
+        Ipld::Bytes(bytes) => json!({ "$bytes": STANDARD_NO_PAD.encode(bytes) }),
+        Ipld::List(list) => Value::Array(list.into_iter().map(json_of_ipld).collect()),
+        Ipld::Map(map) => Value::Object(
+            map.into_iter()
+                .map(|(key, value)| (key, json_of_ipld(value)))
+                .collect(),
+        ),
+        Ipld::Link(cid) => json!({ "$link": cid.to_string() }),
+    }
+}
+
+fn invalid_repo(message: impl Into<String>) -> PluckError {
+    PluckError::InvalidBlueskyRepo(message.into())
+}
+
+/// Reads the header and blocks of a CAR file, with the blocks keyed by their CID.
+///
+/// Every block is checked against its CID, so a corrupted (or tampered with)
+/// export is rejected rather than partly imported.
+fn read_car(car: &[u8]) -> Result<(CarHeader, HashMap<Cid, &[u8]>), PluckError> {
+    let mut rest = car;
+
+    let header = read_section(&mut rest)?;
+    let header: CarHeader = serde_ipld_dagcbor::from_slice(header)
+        .map_err(|err| invalid_repo(format!("invalid CAR header: {}", err)))?;
+
+    let mut blocks = HashMap::new();
+
+    while !rest.is_empty() {
+        let section = read_section(&mut rest)?;
+
+        let mut cursor = Cursor::new(section);
+        let cid = Cid::read_bytes(&mut cursor)
+            .map_err(|err| invalid_repo(format!("invalid block CID: {}", err)))?;
+
+        let block = &section[cursor.position() as usize..];
+        verify_block(&cid, block)?;
+
+        blocks.insert(cid, block);
+    }
+
+    Ok((header, blocks))
+}
+
+/// Checks that a block hashes to the digest in its CID.
+fn verify_block(cid: &Cid, block: &[u8]) -> Result<(), PluckError> {
+    let hash = cid.hash();
+
+    if hash.code() != SHA2_256 {
+        return Err(invalid_repo(format!(
+            "block {} uses unsupported hash function 0x{:x}",
+            cid,
+            hash.code()
+        )));
+    }
+
+    if hash.digest() != Sha256::digest(block).as_slice() {
+        return Err(invalid_repo(format!("block {} doesn't match its CID", cid)));
+    }
+
+    Ok(())
+}
+
+/// Reads a varint-prefixed section of a CAR file, advancing past it.
+fn read_section<'a>(car: &mut &'a [u8]) -> Result<&'a [u8], PluckError> {
+    let mut length: usize = 0;
+    let mut shift = 0;
+
+    loop {
+        let (&byte, rest) = car
+            .split_first()
+            .ok_or_else(|| invalid_repo("unexpected end of CAR file"))?;
+        *car = rest;
+
+        if shift >= usize::BITS {
+            return Err(invalid_repo("section length is too long"));
+        }
+
+        length |= usize::from(byte & 0x7f) << shift;
+        shift += 7;
+
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+
+    if car.len() < length {
+        return Err(invalid_repo("unexpected end of CAR file"));
+    }
+
+    let (section, rest) = car.split_at(length);
+    *car = rest;
+
+    Ok(section)
+}
+
+fn decode_block<T: DeserializeOwned>(
+    blocks: &HashMap<Cid, &[u8]>,
+    cid: &Cid,
+) -> Result<T, PluckError> {
+    let block = blocks
+        .get(cid)
+        .ok_or_else(|| invalid_repo(format!("missing block {}", cid)))?;
+
+    serde_ipld_dagcbor::from_slice(block)
+        .map_err(|err| invalid_repo(format!("invalid block {}: {}", cid, err)))
+}
+
+/// Collects the records in the Merkle Search Tree rooted at the given node,
+/// which is `depth` layers below the root.
+///
+/// Each node may only appear once in the tree, and the tree may only be so
+/// deep, so that a malformed export can't send us round in circles.
+fn walk_mst(
+    blocks: &HashMap<Cid, &[u8]>,
+    cid: &Cid,
+    depth: usize,
+    visited: &mut HashSet<Cid>,
+    entries: &mut Vec<RepoEntry>,
+) -> Result<(), PluckError> {
+    if depth > MAX_MST_DEPTH {
+        return Err(invalid_repo("Merkle Search Tree is too deep"));
+    }
+
+    if !visited.insert(*cid) {
+        return Err(invalid_repo(format!(
+            "Merkle Search Tree node {} appears more than once",
+            cid
+        )));
+    }
+
+    let node: MstNode = decode_block(blocks, cid)?;
+
+    if let Some(left) = &node.l {
+        walk_mst(blocks, left, depth + 1, visited, entries)?;
+    }
+
+    // Keys are prefix-compressed against the previous key in the same node.
+    let mut key = Vec::new();
+
+    for entry in node.e {
+        key.truncate(entry.p);
+        key.extend_from_slice(&entry.k);
+
+        let path =
+            std::str::from_utf8(&key).map_err(|_| invalid_repo("record key is not valid UTF-8"))?;
+
+        let (collection, rkey) = path
+            .split_once('/')
+            .ok_or_else(|| invalid_repo(format!("invalid record key {}", path)))?;
+
+        // Records that weren't included in the export are skipped.
+        if let Some(data) = blocks.get(&entry.v) {
+            entries.push(RepoEntry {
+                collection: collection.to_string(),
+                rkey: rkey.to_string(),
+                cid: entry.v,
+                data: data.to_vec(),
+            });
+        }
+
+        if let Some(right) = &entry.t {
+            walk_mst(blocks, right, depth + 1, visited, entries)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// A type of record that can be read from a [`BlueskyRepo`].
+pub trait RepoRecord: Record {
+    /// The collection the records are stored in, e.g. `app.bsky.feed.post`.
+    const NSID: &'static str;
+
+    /// The lexicon type the records are decoded as.
+    type Data: DeserializeOwned;
+
+    /// Converts a record, given its URI and CID.
+    fn from_repo(uri: String, cid: String, data: Self::Data) -> Result<Self, String>;
+}
+
+impl RepoRecord for BlueskyPost {
+    const NSID: &'static str = "app.bsky.feed.post";
+
+    type Data = feed::post::RecordData;
+
+    fn from_repo(uri: String, cid: String, data: Self::Data) -> Result<Self, String> {
+        Ok(BlueskyPost {
+            cid: Some(cid),
+            ..post_of_record(uri, data)
+        })
+    }
+}
+
+/// The subject of a like or repost record.
+struct Subject {
+    post_uri: String,
+    post_cid: String,
+    author_did: String,
+}
+
+impl Subject {
+    fn of(strong_ref: strong_ref::Main) -> Result<Self, String> {
+        let strong_ref::MainData { cid, uri } = strong_ref.data;
+
+        Ok(Self {
+            author_did: did_of_uri(&uri)
+                .ok_or_else(|| format!("{} is not an at:// URI with a DID", uri))?
+                .to_string(),
+            post_cid: cid.as_ref().to_string(),
+            post_uri: uri,
+        })
+    }
+}
+
+impl RepoRecord for BlueskyLike {
+    const NSID: &'static str = "app.bsky.feed.like";
+
+    type Data = feed::like::RecordData;
+
+    fn from_repo(uri: String, _cid: String, data: Self::Data) -> Result<Self, String> {
+        let subject = Subject::of(data.subject)?;
+
+        Ok(BlueskyLike {
+            uri: Some(uri),
+            liked_at: data.created_at.as_ref().to_utc(),
+            post_uri: subject.post_uri,
+            post_cid: subject.post_cid,
+            author_did: subject.author_did,
+            author_handle: None,
+            text: None,
+            created_at: None,
+        })
+    }
+}
+
+impl RepoRecord for BlueskyRepost {
+    const NSID: &'static str = "app.bsky.feed.repost";
+
+    type Data = feed::repost::RecordData;
+
+    fn from_repo(_uri: String, _cid: String, data: Self::Data) -> Result<Self, String> {
+        let subject = Subject::of(data.subject)?;
+
+        Ok(BlueskyRepost {
+            reposted_at: data.created_at.as_ref().to_utc(),
+            post_uri: subject.post_uri,
+            post_cid: subject.post_cid,
+            author_did: subject.author_did,
+            author_handle: None,
+            text: None,
+            created_at: None,
+        })
+    }
+}
+
+impl RepoRecord for BlueskyFollow {
+    const NSID: &'static str = "app.bsky.graph.follow";
+
+    type Data = graph::follow::RecordData;
+
+    fn from_repo(uri: String, _cid: String, data: Self::Data) -> Result<Self, String> {
+        Ok(BlueskyFollow {
+            uri,
+            subject_did: data.subject.to_string(),
+            created_at: data.created_at.as_ref().to_utc(),
+        })
+    }
+}
+
+impl RepoRecord for BlueskyBlock {
+    const NSID: &'static str = "app.bsky.graph.block";
+
+    type Data = graph::block::RecordData;
+
+    fn from_repo(uri: String, _cid: String, data: Self::Data) -> Result<Self, String> {
+        Ok(BlueskyBlock {
+            uri,
+            subject_did: data.subject.to_string(),
+            created_at: data.created_at.as_ref().to_utc(),
+        })
+    }
+}
+
+/// Imports the records of type `T` from a [`BlueskyRepo`].
+pub struct BlueskyRepoImporter<T> {
+    repo: Arc<BlueskyRepo>,
+    records: PhantomData<fn() -> T>,
+}
+
+impl<T: RepoRecord> BlueskyRepoImporter<T> {
+    pub fn new(repo: Arc<BlueskyRepo>) -> Self {
+        Self {
+            repo,
+            records: PhantomData,
+        }
+    }
+}
+
+#[async_trait]
+impl<T: RepoRecord> Source for BlueskyRepoImporter<T> {
+    type Item = T;
+    type Cursor = ();
+
+    fn supports_incremental_sync(&self) -> bool {
+        false
+    }
+
+    async fn fetch_page(&mut self, _cursor: Option<()>) -> Result<Page<T, ()>, PluckError> {
+        Ok(Page {
+            items: self.repo.records(),
+            next_cursor: None,
+            newest_timestamp: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use ipld_core::cid::multihash::Multihash;
+
+    use super::*;
+    use crate::models::BlueskyEmbed;
+
+    /// A repository with a post, a post without a `createdAt`, a like, a
+    /// repost, a follow and a block, split across two MST nodes.
+    const FIXTURE: &[u8] = include_bytes!("../../tests/fixtures/bluesky/repo.car");
+
+    fn block(ipld: &Ipld) -> (Cid, Vec<u8>) {
+        let bytes = serde_ipld_dagcbor::to_vec(ipld).unwrap();
+        let digest = Sha256::digest(&bytes);
+
+        (
+            Cid::new_v1(0x71, Multihash::wrap(SHA2_256, &digest).unwrap()),
+            bytes,
+        )
+    }
+
+    fn map(pairs: Vec<(&str, Ipld)>) -> Ipld {
+        Ipld::Map(
+            pairs
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect::<BTreeMap<_, _>>(),
+        )
+    }
+
+    fn push_varint(mut value: usize, out: &mut Vec<u8>) {
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+
+            if value == 0 {
+                out.push(byte);
+                return;
+            }
+
+            out.push(byte | 0x80);
+        }
+    }
+
+    /// Builds a CAR file with a commit pointing at the given MST root.
+    fn car(mst_root: Cid, mut blocks: Vec<(Cid, Vec<u8>)>) -> Vec<u8> {
+        let commit = block(&map(vec![
+            ("did", Ipld::String("did:plc:test".to_string())),
+            ("data", Ipld::Link(mst_root)),
+        ]));
+
+        let header = serde_ipld_dagcbor::to_vec(&map(vec![
+            ("version", Ipld::Integer(1)),
+            ("roots", Ipld::List(vec![Ipld::Link(commit.0)])),
+        ]))
+        .unwrap();
+
+        blocks.push(commit);
+
+        let mut car = Vec::new();
+        push_varint(header.len(), &mut car);
+        car.extend(header);
+
+        for (cid, bytes) in blocks {
+            let cid = cid.to_bytes();
+            push_varint(cid.len() + bytes.len(), &mut car);
+            car.extend(cid);
+            car.extend(bytes);
+        }
+
+        car
+    }
+
+    fn mst_node(left: Option<Cid>, entries: Vec<Ipld>) -> (Cid, Vec<u8>) {
+        block(&map(vec![
+            ("l", left.map_or(Ipld::Null, Ipld::Link)),
+            ("e", Ipld::List(entries)),
+        ]))
+    }
+
+    fn error_message(result: Result<BlueskyRepo, PluckError>) -> String {
+        match result {
+            Err(PluckError::InvalidBlueskyRepo(message)) => message,
+            Err(err) => panic!("unexpected error: {}", err),
+            Ok(_) => panic!("expected the repository to be rejected"),
+        }
+    }
+
+    #[test]
+    fn reads_every_record_in_the_fixture() {
+        let repo = BlueskyRepo::from_car(FIXTURE).unwrap();
+
+        assert_eq!(repo.did(), "did:plc:me");
+
+        // The post without a `createdAt` is skipped.
+        let posts = repo.records::<BlueskyPost>();
+        assert_eq!(posts.len(), 1);
+
+        let post = &posts[0];
+        assert_eq!(post.uri, "at://did:plc:me/app.bsky.feed.post/3kaaaaaaaaaa2");
+        assert_eq!(post.text, "hello");
+        assert_eq!(post.langs, Some(vec!["en".to_string()]));
+        assert!(post.cid.is_some());
+        assert!(matches!(post.embed, Some(BlueskyEmbed::Images { .. })));
+
+        let in_reply_to = post.in_reply_to.as_ref().unwrap();
+        assert_eq!(in_reply_to.author_did, "did:plc:other");
+
+        let likes = repo.records::<BlueskyLike>();
+        assert_eq!(likes.len(), 1);
+        assert_eq!(
+            likes[0].post_uri,
+            "at://did:plc:other/app.bsky.feed.post/3jzfcijpj2z2a"
+        );
+        assert_eq!(
+            likes[0].uri.as_deref(),
+            Some("at://did:plc:me/app.bsky.feed.like/3kaaaaaaaaaa2")
+        );
+
+        assert_eq!(repo.records::<BlueskyRepost>().len(), 1);
+
+        let follows = repo.records::<BlueskyFollow>();
+        assert_eq!(follows.len(), 1);
+        assert_eq!(follows[0].subject_did, "did:plc:friend");
+
+        let blocks = repo.records::<BlueskyBlock>();
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].subject_did, "did:plc:troll");
+    }
+
+    #[test]
+    fn rejects_a_truncated_car() {
+        let message = error_message(BlueskyRepo::from_car(&FIXTURE[..FIXTURE.len() - 3]));
+
+        assert_eq!(message, "unexpected end of CAR file");
+    }
+
+    #[test]
+    fn rejects_a_block_that_does_not_match_its_cid() {
+        let mut car = FIXTURE.to_vec();
+
+        let text = car
+            .windows(5)
+            .position(|window| window == b"hello")
+            .unwrap();
+        car[text] = b'j';
+
+        let message = error_message(BlueskyRepo::from_car(&car));
+
+        assert!(message.ends_with("doesn't match its CID"), "{}", message);
+    }
+
+    #[test]
+    fn rejects_a_node_that_appears_twice() {
+        let record = block(&map(vec![("text", Ipld::String("hi".to_string()))]));
+
+        let entry = |key: &str, subtree: Option<Cid>| {
+            map(vec![
+                ("p", Ipld::Integer(0)),
+                ("k", Ipld::Bytes(key.as_bytes().to_vec())),
+                ("v", Ipld::Link(record.0)),
+                ("t", subtree.map_or(Ipld::Null, Ipld::Link)),
+            ])
+        };
+
+        let shared = mst_node(None, vec![entry("app.bsky.feed.post/a", None)]);
+        let root = mst_node(
+            Some(shared.0),
+            vec![entry("app.bsky.feed.post/b", Some(shared.0))],
+        );
+
+        let car = car(root.0, vec![record, shared, root]);
+        let message = error_message(BlueskyRepo::from_car(&car));
+
+        assert!(message.ends_with("appears more than once"), "{}", message);
+    }
+
+    #[test]
+    fn rejects_a_tree_that_is_too_deep() {
+        let mut blocks = vec![mst_node(None, Vec::new())];
+
+        for _ in 0..=MAX_MST_DEPTH {
+            let child = blocks.last().unwrap().0;
+            blocks.push(mst_node(Some(child), Vec::new()));
+        }
+
+        let root = blocks.last().unwrap().0;
+        let message = error_message(BlueskyRepo::from_car(&car(root, blocks)));
+
+        assert_eq!(message, "Merkle Search Tree is too deep");
+    }
+}
@@ -0,0 +1,103 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use atrium_api::agent::store::SessionStore;
+use atrium_api::agent::Session;
+use tokio::sync::RwLock;
+
+use crate::storage::write_private;
+use crate::PluckError;
+
+/// The directory that Bluesky sessions are saved in, so that later runs can
+/// resume them instead of logging in again.
+pub const SESSION_CACHE_DIR: &str = ".cache/bluesky/sessions";
+
+/// Returns the path that the session for the given handle is saved at.
+pub fn session_path(handle: &str) -> PathBuf {
+    Path::new(SESSION_CACHE_DIR).join(format!("{}.json", handle))
+}
+
+/// A [`SessionStore`] that keeps the session in memory and, if it has a path,
+/// saves it to a file.
+///
+/// Logging in with an app password is rate limited, so saving the session (and
+/// every refreshed copy of it) lets frequent syncs reuse a single login.
+#[derive(Debug, Clone, Default)]
+pub struct BlueskySessionStore {
+    path: Option<PathBuf>,
+    session: Arc<RwLock<Option<Session>>>,
+}
+
+impl BlueskySessionStore {
+    /// Creates a store that only keeps the session in memory.
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+
+    /// Creates a store that saves the session to the given file.
+    pub fn file(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: Some(path.into()),
+            session: Arc::default(),
+        }
+    }
+
+    /// Returns the session saved by a previous run, if there is one.
+    pub async fn saved_session(&self) -> Option<Session> {
+        let path = self.path.as_ref()?;
+
+        let contents = tokio::fs::read(path).await.ok()?;
+
+        match serde_json::from_slice(&contents) {
+            Ok(session) => Some(session),
+            Err(err) => {
+                eprintln!("Ignoring invalid session file {}: {}", path.display(), err);
+                None
+            }
+        }
+    }
+
+    async fn save(&self, path: &Path, session: &Session) -> Result<(), PluckError> {
+        let contents =
+            serde_json::to_vec_pretty(session).map_err(|err| PluckError::storage(path, err))?;
+
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|err| PluckError::storage(parent, err))?;
+        }
+
+        // The session holds the account's tokens, so keep it private.
+        write_private(path, &contents).await
+    }
+}
+
+// A session that can't be saved only costs us a login on the next run, so
+// failures are logged rather than failing the sync.
+impl SessionStore for BlueskySessionStore {
+    async fn get_session(&self) -> Option<Session> {
+        self.session.read().await.clone()
+    }
+
+    async fn set_session(&self, session: Session) {
+        if let Some(path) = &self.path {
+            if let Err(err) = self.save(path, &session).await {
+                eprintln!("Failed to save the Bluesky session: {}", err);
+            }
+        }
+
+        self.session.write().await.replace(session);
+    }
+
+    async fn clear_session(&self) {
+        if let Some(path) = &self.path {
+            if let Err(err) = tokio::fs::remove_file(path).await {
+                if err.kind() != std::io::ErrorKind::NotFound {
+                    eprintln!("Failed to remove {}: {}", path.display(), err);
+                }
+            }
+        }
+
+        self.session.write().await.take();
+    }
+}
@@ -0,0 +1,96 @@
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::storage::Storage;
+use crate::PluckError;
+
+/// The key that a sync's progress is checkpointed under.
+const SYNC_STATE_KEY: &str = "sync-state";
+
+/// The key that the high-water mark is stored under.
+const HIGH_WATER_MARK_KEY: &str = "high-water-mark";
+
+/// The progress of an in-flight sync, used to resume it if it gets interrupted.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SyncState<C> {
+    /// Whether the sync being checkpointed is a full sync.
+    pub full_sync: bool,
+
+    /// The cursor of the next page to fetch.
+    pub cursor: C,
+
+    /// The number of pages fetched so far.
+    pub pages_fetched: usize,
+
+    /// The number of new items written to the archive so far.
+    pub items_written: usize,
+
+    /// The newest item fetched so far.
+    pub newest_item: Option<HighWaterMark>,
+
+    pub updated_at: DateTime<Utc>,
+}
+
+impl<C> SyncState<C> {
+    /// Removes the sync state for the given collection, once the sync has completed.
+    pub async fn clear(storage: &impl Storage, collection: &str) -> Result<(), PluckError> {
+        storage.clear_state(collection, SYNC_STATE_KEY).await
+    }
+}
+
+impl<C: DeserializeOwned> SyncState<C> {
+    /// Loads the sync state for the given collection, if a sync of it was interrupted.
+    pub async fn load(
+        storage: &impl Storage,
+        collection: &str,
+    ) -> Result<Option<Self>, PluckError> {
+        storage.read_state(collection, SYNC_STATE_KEY).await
+    }
+}
+
+impl<C: Serialize + Sync> SyncState<C> {
+    /// Saves the sync state for the given collection.
+    pub async fn save(&self, storage: &impl Storage, collection: &str) -> Result<(), PluckError> {
+        storage.write_state(collection, SYNC_STATE_KEY, self).await
+    }
+}
+
+/// The newest item that has been archived from a source.
+///
+/// Incremental syncs fetch items until they reach items older than the
+/// high-water mark (minus an overlap window), rather than stopping at the first
+/// item that's already in the archive.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HighWaterMark {
+    /// The ID of the newest item.
+    pub id: String,
+
+    /// When the newest item was created.
+    pub timestamp: DateTime<Utc>,
+}
+
+impl HighWaterMark {
+    /// Loads the high-water mark for the given collection, if one has been recorded.
+    pub async fn load(
+        storage: &impl Storage,
+        collection: &str,
+    ) -> Result<Option<Self>, PluckError> {
+        storage.read_state(collection, HIGH_WATER_MARK_KEY).await
+    }
+
+    /// Saves the high-water mark for the given collection.
+    pub async fn save(&self, storage: &impl Storage, collection: &str) -> Result<(), PluckError> {
+        storage
+            .write_state(collection, HIGH_WATER_MARK_KEY, self)
+            .await
+    }
+
+    /// Returns whichever of the two high-water marks is newer.
+    pub fn newest(self, other: Option<Self>) -> Self {
+        match other {
+            Some(other) if other.timestamp > self.timestamp => other,
+            _ => self,
+        }
+    }
+}
@@ -0,0 +1,352 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use indexmap::IndexMap;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::retry::RetryPolicy;
+use crate::storage::{Partitioning, StorageFormat};
+use crate::PluckError;
+
+/// The config file that is read from the working directory when no other is given.
+pub const DEFAULT_CONFIG_FILE_NAME: &str = "pluck.toml";
+
+/// The contents of a `pluck.toml` config file, with a section for each source.
+///
+/// ```toml
+/// [lastfm]
+/// user = "maxdeviant"
+/// api_key = { env = "LASTFM_API_KEY" }
+/// output_dir = "archive/lastfm"
+/// format = "jsonl"
+/// partition_by = "month"
+///
+/// [lastfm.rate_limit]
+/// page_delay_ms = 500
+/// max_attempts = 3
+/// ```
+///
+/// Sources with more than one account list them under `accounts`, and each
+/// account is synced into its own subdirectory of the `output_dir`:
+///
+/// ```toml
+/// [bluesky]
+/// output_dir = "archive/bluesky"
+///
+/// [bluesky.accounts.personal]
+/// handle = "maxdeviant.com"
+/// app_password = { env = "BLUESKY_PERSONAL_APP_PASSWORD" }
+///
+/// [bluesky.accounts.work]
+/// handle = "work.example.com"
+/// app_password = { env = "BLUESKY_WORK_APP_PASSWORD" }
+/// ```
+#[derive(Debug, Default, Deserialize)]
+pub struct Config {
+    pub bluesky: Option<SourceConfig<BlueskyAccount>>,
+    pub lastfm: Option<SourceConfig<LastfmAccount>>,
+    pub twitter: Option<SourceConfig<TwitterAccount>>,
+}
+
+impl Config {
+    /// Loads the config file at the given path.
+    pub fn load(path: &Path) -> Result<Self, PluckError> {
+        let contents = std::fs::read_to_string(path).map_err(|err| ConfigError::Read {
+            path: path.to_owned(),
+            source: err,
+        })?;
+
+        let config = toml::from_str(&contents).map_err(|err| ConfigError::Parse {
+            path: path.to_owned(),
+            source: err,
+        })?;
+
+        Ok(config)
+    }
+
+    /// Loads the config file at the given path, or the [`DEFAULT_CONFIG_FILE_NAME`]
+    /// if no path is given.
+    ///
+    /// An empty config is returned if no path is given and there is no default
+    /// config file.
+    pub fn load_or_default(path: Option<&Path>) -> Result<Self, PluckError> {
+        match path {
+            Some(path) => Self::load(path),
+            None => {
+                let path = Path::new(DEFAULT_CONFIG_FILE_NAME);
+
+                if path.exists() {
+                    Self::load(path)
+                } else {
+                    Ok(Self::default())
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct BlueskyAccount {
+    pub handle: Option<String>,
+    pub app_password: Option<Credential>,
+
+    /// Archive the account from the public AppView, without logging in.
+    pub public: Option<bool>,
+
+    /// The URL of the PDS to log in to, for accounts whose handle doesn't
+    /// resolve to it.
+    pub pds: Option<String>,
+
+    /// Also archive reposts, into the `reposts` subdirectory.
+    pub include_reposts: Option<bool>,
+
+    /// Also archive likes, into the `likes` subdirectory.
+    pub include_likes: Option<bool>,
+
+    /// Also archive every reply to the account's own threads, into the
+    /// `threads` subdirectory.
+    pub include_threads: Option<bool>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct LastfmAccount {
+    pub user: Option<String>,
+    pub api_key: Option<Credential>,
+
+    /// Also archive each scrobble's MusicBrainz IDs, Last.fm URLs, album art
+    /// and loved flag.
+    pub include_metadata: Option<bool>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct TwitterAccount {
+    pub screen_name: Option<String>,
+    pub consumer_key: Option<Credential>,
+    pub consumer_secret: Option<Credential>,
+}
+
+/// The config for a source, along with the accounts to sync from it.
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct SourceConfig<A> {
+    /// The account configured at the top level of the source's section, for
+    /// sources with a single account.
+    #[serde(flatten)]
+    pub account: A,
+
+    /// The named accounts to sync, each into its own subdirectory.
+    #[serde(default = "IndexMap::new")]
+    pub accounts: IndexMap<String, A>,
+
+    /// The directory to store the archive in.
+    pub output_dir: Option<PathBuf>,
+
+    pub format: Option<StorageFormat>,
+
+    pub partition_by: Option<Partitioning>,
+
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+}
+
+impl<A> SourceConfig<A> {
+    /// Returns the accounts to sync: the named accounts if there are any,
+    /// otherwise the unnamed one.
+    pub fn accounts(&self) -> Vec<(Option<&str>, &A)> {
+        if self.accounts.is_empty() {
+            return vec![(None, &self.account)];
+        }
+
+        self.accounts
+            .iter()
+            .map(|(name, account)| (Some(name.as_str()), account))
+            .collect()
+    }
+
+    /// Returns the account with the given name, or the only configured account
+    /// if no name is given.
+    pub fn account(
+        &self,
+        source_name: &'static str,
+        name: Option<&str>,
+    ) -> Result<(Option<&str>, &A), PluckError> {
+        if let Some(name) = name {
+            return self
+                .accounts
+                .get_key_value(name)
+                .map(|(name, account)| (Some(name.as_str()), account))
+                .ok_or_else(|| {
+                    ConfigError::UnknownAccount {
+                        source_name,
+                        account: name.to_owned(),
+                    }
+                    .into()
+                });
+        }
+
+        match &self.accounts()[..] {
+            [account] => Ok(*account),
+            _ => Err(ConfigError::AmbiguousAccount { source_name }.into()),
+        }
+    }
+
+    /// Returns the directory to store the given account's archive in.
+    pub fn output_dir(&self, account_name: Option<&str>) -> Option<PathBuf> {
+        let output_dir = self.output_dir.as_ref()?;
+
+        Some(match account_name {
+            Some(account_name) => output_dir.join(account_name),
+            None => output_dir.clone(),
+        })
+    }
+}
+
+/// How quickly a source is allowed to make requests.
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct RateLimitConfig {
+    /// The delay (in milliseconds) between pages, replacing the source's own pacing.
+    pub page_delay_ms: Option<u64>,
+
+    pub max_attempts: Option<u32>,
+    pub initial_backoff_ms: Option<u64>,
+    pub max_backoff_ms: Option<u64>,
+}
+
+impl RateLimitConfig {
+    pub fn page_delay(&self) -> Option<Duration> {
+        self.page_delay_ms.map(Duration::from_millis)
+    }
+
+    /// Returns the retry policy, falling back to the default for any unset options.
+    pub fn retry_policy(&self) -> RetryPolicy {
+        let default = RetryPolicy::default();
+
+        RetryPolicy {
+            max_attempts: self.max_attempts.unwrap_or(default.max_attempts).max(1),
+            initial_backoff: self
+                .initial_backoff_ms
+                .map(Duration::from_millis)
+                .unwrap_or(default.initial_backoff),
+            max_backoff: self
+                .max_backoff_ms
+                .map(Duration::from_millis)
+                .unwrap_or(default.max_backoff),
+        }
+    }
+}
+
+/// A reference to a secret, so that secrets don't need to live in the config file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum Credential {
+    /// Read from an environment variable, e.g. `{ env = "LASTFM_API_KEY" }`.
+    Env { env: String },
+
+    /// Read from a file, e.g. `{ file = "/run/secrets/lastfm" }`.
+    File { file: PathBuf },
+
+    /// Given inline.
+    Value(String),
+}
+
+impl Credential {
+    pub fn env(name: impl Into<String>) -> Self {
+        Self::Env { env: name.into() }
+    }
+
+    /// Returns the value of the secret.
+    pub fn resolve(&self) -> Result<String, PluckError> {
+        match self {
+            Self::Env { env } => {
+                std::env::var(env).map_err(|_| ConfigError::MissingEnvVar(env.clone()).into())
+            }
+            Self::File { file } => std::fs::read_to_string(file)
+                .map(|contents| contents.trim().to_owned())
+                .map_err(|err| {
+                    ConfigError::Read {
+                        path: file.clone(),
+                        source: err,
+                    }
+                    .into()
+                }),
+            Self::Value(value) => Ok(value.clone()),
+        }
+    }
+}
+
+/// The underlying cause of a [`PluckError::Config`] error.
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("failed to read {}: {source}", path.display())]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("failed to parse {}: {source}", path.display())]
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+
+    #[error("environment variable {0} is not set")]
+    MissingEnvVar(String),
+
+    #[error("no account named {account:?} is configured for {source_name}")]
+    UnknownAccount {
+        source_name: &'static str,
+        account: String,
+    },
+
+    #[error(
+        "multiple accounts are configured for {source_name}, so one must be chosen with --account"
+    )]
+    AmbiguousAccount { source_name: &'static str },
+
+    /// A required option was neither given on the command line nor in the config file.
+    #[error("no {option} configured for {source_name}")]
+    Missing {
+        source_name: &'static str,
+        option: &'static str,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn credentials_can_be_read_from_the_environment_or_a_file() {
+        let secret_path = std::env::temp_dir().join("pluck-config-test-secret");
+        std::fs::write(&secret_path, "from-file\n").unwrap();
+        std::env::set_var("PLUCK_CONFIG_TEST_SECRET", "from-env");
+
+        let config: Config = toml::from_str(&format!(
+            r#"
+            [lastfm]
+            api_key = "inline"
+
+            [lastfm.accounts.env]
+            api_key = {{ env = "PLUCK_CONFIG_TEST_SECRET" }}
+
+            [lastfm.accounts.file]
+            api_key = {{ file = {:?} }}
+
+            [lastfm.accounts.missing]
+            api_key = {{ env = "PLUCK_CONFIG_TEST_MISSING" }}
+            "#,
+            secret_path
+        ))
+        .unwrap();
+
+        let lastfm = config.lastfm.unwrap();
+        let api_key = |account: &LastfmAccount| account.api_key.as_ref().unwrap().resolve();
+
+        assert_eq!(api_key(&lastfm.account).unwrap(), "inline");
+        assert_eq!(api_key(&lastfm.accounts["env"]).unwrap(), "from-env");
+        assert_eq!(api_key(&lastfm.accounts["file"]).unwrap(), "from-file");
+        assert!(api_key(&lastfm.accounts["missing"]).is_err());
+
+        std::fs::remove_file(secret_path).unwrap();
+    }
+}
@@ -0,0 +1,308 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use indexmap::IndexSet;
+
+use crate::bluesky::REPO_CAR_FILE_NAME;
+use crate::checkpoint::HighWaterMark;
+use crate::media::MEDIA_DIR_NAME;
+use crate::models::Record;
+use crate::storage::{write_atomic, Partition, Storage};
+use crate::{PluckError, StorageError};
+
+/// Copies every `T` record from one storage into another, along with the
+/// high-water mark.
+///
+/// Records are regrouped according to the target storage's partitioning, so
+/// this can also be used to repartition an archive. Before anything is
+/// written, the regrouped records are counted to verify that there are as many
+/// as were read from the source, so none are lost along the way (e.g. by being
+/// merged with another record when regrouped).
+///
+/// Returns the number of records converted.
+pub async fn convert<T: Record>(
+    source: &impl Storage,
+    target: &impl Storage,
+    target_location: &Path,
+) -> Result<usize, PluckError> {
+    let partitioning = target.partitioning();
+
+    let mut records_by_partition: BTreeMap<Partition, IndexSet<T>> = BTreeMap::new();
+    let mut expected = 0;
+
+    for partition in source.partitions::<T>().await? {
+        let records = source
+            .read_partition::<T>(partition)
+            .await?
+            .unwrap_or_default();
+
+        println!(
+            "Reading {} {} from {}",
+            records.len(),
+            T::COLLECTION,
+            partition
+        );
+
+        expected += records.len();
+
+        for record in records {
+            records_by_partition
+                .entry(partitioning.partition_of(record.timestamp()))
+                .or_default()
+                .insert(record);
+        }
+    }
+
+    let actual = records_by_partition.values().map(IndexSet::len).sum();
+
+    if actual != expected {
+        return Err(PluckError::storage(
+            target_location,
+            StorageError::CountMismatch {
+                collection: T::COLLECTION,
+                expected,
+                actual,
+            },
+        ));
+    }
+
+    for (partition, records) in &mut records_by_partition {
+        records.sort_unstable_by(|a, b| b.sort_key().cmp(&a.sort_key()));
+
+        target.write_partition(*partition, records).await?;
+    }
+
+    if let Some(high_water_mark) = HighWaterMark::load(source, T::COLLECTION).await? {
+        high_water_mark.save(target, T::COLLECTION).await?;
+    }
+
+    Ok(actual)
+}
+
+/// Copies the files that are archived alongside the records, namely the
+/// downloaded media and the Bluesky repository, from one archive directory to
+/// another.
+///
+/// Files that already exist in the target directory are left alone, as media
+/// is named after its contents.
+pub async fn copy_archive_files(source_dir: &Path, target_dir: &Path) -> Result<(), PluckError> {
+    let mut dirs = vec![PathBuf::from(MEDIA_DIR_NAME)];
+
+    copy_if_missing(
+        &source_dir.join(REPO_CAR_FILE_NAME),
+        &target_dir.join(REPO_CAR_FILE_NAME),
+    )
+    .await?;
+
+    while let Some(dir) = dirs.pop() {
+        let source_subdir = source_dir.join(&dir);
+
+        if !source_subdir.is_dir() {
+            continue;
+        }
+
+        let mut entries = tokio::fs::read_dir(&source_subdir)
+            .await
+            .map_err(|err| PluckError::storage(&source_subdir, err))?;
+
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .map_err(|err| PluckError::storage(&source_subdir, err))?
+        {
+            let path = dir.join(entry.file_name());
+
+            if entry.path().is_dir() {
+                dirs.push(path);
+            } else {
+                copy_if_missing(&source_dir.join(&path), &target_dir.join(&path)).await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn copy_if_missing(source: &Path, target: &Path) -> Result<(), PluckError> {
+    if !source.is_file() || target.exists() {
+        return Ok(());
+    }
+
+    if let Some(parent) = target.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .map_err(|err| PluckError::storage(parent, err))?;
+    }
+
+    let contents = tokio::fs::read(source)
+        .await
+        .map_err(|err| PluckError::storage(source, err))?;
+
+    write_atomic(target, &contents, false).await
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::*;
+    use crate::models::BlueskyFollow;
+    use crate::storage::{Partitioning, StorageFormat};
+
+    fn follow(rkey: &str, month: u32) -> BlueskyFollow {
+        BlueskyFollow {
+            uri: format!("at://did:plc:me/app.bsky.graph.follow/{}", rkey),
+            subject_did: format!("did:plc:{}", rkey),
+            created_at: Utc.with_ymd_and_hms(2024, month, 1, 12, 0, 0).unwrap(),
+        }
+    }
+
+    /// Returns an empty directory for a test to write to.
+    fn empty_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("pluck-{}-{}", name, std::process::id()));
+
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    #[tokio::test]
+    async fn records_are_regrouped_into_the_target_partitioning() {
+        let dir = empty_dir("convert");
+        let source = StorageFormat::Toml
+            .open(&dir.join("source"), Partitioning::Month, false)
+            .unwrap();
+        let target = StorageFormat::Sqlite
+            .open(&dir, Partitioning::Year, false)
+            .unwrap();
+
+        let march = Partition::Month {
+            year: 2024,
+            month: 3,
+        };
+        let april = Partition::Month {
+            year: 2024,
+            month: 4,
+        };
+
+        source
+            .write_partition(march, &IndexSet::from([follow("b", 3), follow("a", 3)]))
+            .await
+            .unwrap();
+        source
+            .write_partition(april, &IndexSet::from([follow("c", 4)]))
+            .await
+            .unwrap();
+
+        HighWaterMark {
+            id: follow("c", 4).uri,
+            timestamp: follow("c", 4).created_at,
+        }
+        .save(&source, BlueskyFollow::COLLECTION)
+        .await
+        .unwrap();
+
+        let count = convert::<BlueskyFollow>(&source, &target, &dir)
+            .await
+            .unwrap();
+
+        assert_eq!(count, 3);
+        assert_eq!(
+            target.partitions::<BlueskyFollow>().await.unwrap(),
+            vec![Partition::Year(2024)]
+        );
+
+        let follows = target
+            .read_partition::<BlueskyFollow>(Partition::Year(2024))
+            .await
+            .unwrap()
+            .unwrap();
+        let uris = follows
+            .iter()
+            .map(|follow| follow.uri.rsplit('/').next().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(uris, vec!["c", "b", "a"]);
+
+        let high_water_mark = HighWaterMark::load(&target, BlueskyFollow::COLLECTION)
+            .await
+            .unwrap();
+        assert_eq!(high_water_mark.unwrap().id, follow("c", 4).uri);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn records_that_would_be_merged_are_caught_before_anything_is_written() {
+        let dir = empty_dir("convert-merged");
+        let source = StorageFormat::Json
+            .open(&dir.join("source"), Partitioning::Month, false)
+            .unwrap();
+        let target = StorageFormat::Jsonl
+            .open(&dir.join("target"), Partitioning::Single, false)
+            .unwrap();
+
+        // The same record, archived in two partitions.
+        for month in [3, 4] {
+            source
+                .write_partition(
+                    Partition::Month { year: 2024, month },
+                    &IndexSet::from([follow("a", 3)]),
+                )
+                .await
+                .unwrap();
+        }
+
+        let result = convert::<BlueskyFollow>(&source, &target, &dir).await;
+
+        assert!(matches!(
+            result,
+            Err(PluckError::Storage {
+                source: StorageError::CountMismatch {
+                    expected: 2,
+                    actual: 1,
+                    ..
+                },
+                ..
+            })
+        ));
+        assert!(target
+            .partitions::<BlueskyFollow>()
+            .await
+            .unwrap()
+            .is_empty());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn media_and_the_repository_are_copied() {
+        let dir = empty_dir("convert-files");
+        let source_dir = dir.join("source");
+        let target_dir = dir.join("target");
+
+        std::fs::create_dir_all(source_dir.join("media/ab")).unwrap();
+        std::fs::write(source_dir.join("media/ab/ab12.jpg"), "photo").unwrap();
+        std::fs::write(source_dir.join("repo.car"), "repo").unwrap();
+
+        copy_archive_files(&source_dir, &target_dir).await.unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(target_dir.join("media/ab/ab12.jpg")).unwrap(),
+            "photo"
+        );
+        assert_eq!(
+            std::fs::read_to_string(target_dir.join("repo.car")).unwrap(),
+            "repo"
+        );
+
+        // Copying into the same directory leaves the files as they are.
+        copy_archive_files(&source_dir, &source_dir).await.unwrap();
+        assert_eq!(
+            std::fs::read_to_string(source_dir.join("repo.car")).unwrap(),
+            "repo"
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
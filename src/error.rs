@@ -0,0 +1,206 @@
+use std::fmt::{Debug, Display};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use atrium_api::xrpc;
+use chrono::{DateTime, Utc};
+use thiserror::Error;
+
+use crate::bluesky::RetryAfterResponse;
+use crate::config::ConfigError;
+
+/// An error that occurred while fetching or archiving items.
+#[derive(Debug, Error)]
+pub enum PluckError {
+    /// The request could not be sent or its response could not be read.
+    #[error("HTTP request failed: {0}")]
+    Http(#[from] reqwest::Error),
+
+    /// The server responded with an unsuccessful status code.
+    #[error("{url} responded with {status}")]
+    HttpStatus {
+        url: String,
+        status: reqwest::StatusCode,
+
+        /// How long the server asked us to wait before retrying, from its `Retry-After` header.
+        retry_after: Option<Duration>,
+    },
+
+    /// Last.fm responded with an [error payload](https://www.last.fm/api/errorcodes).
+    #[error("Last.fm API error {code}: {message}")]
+    LastfmApi { code: i32, message: String },
+
+    /// Bluesky responded with an XRPC error.
+    #[error("Bluesky API error ({status}): {message}")]
+    BlueskyApi {
+        status: u16,
+        message: String,
+
+        /// How long Bluesky asked us to wait before retrying, from its
+        /// `Retry-After` or `ratelimit-reset` header.
+        retry_after: Option<Duration>,
+    },
+
+    /// The request to Bluesky could not be sent or its response could not be read.
+    #[error("Bluesky HTTP request failed: {0}")]
+    BlueskyHttp(String),
+
+    #[error("Bluesky request failed: {0}")]
+    Bluesky(String),
+
+    /// A Bluesky repository export (a CAR file) could not be parsed.
+    #[error("invalid Bluesky repository: {0}")]
+    InvalidBlueskyRepo(String),
+
+    #[error("Twitter request failed: {0}")]
+    Twitter(#[from] egg_mode::error::Error),
+
+    /// A response or file could not be deserialized.
+    #[error("failed to deserialize {context}: {source}")]
+    Deserialize {
+        /// What was being deserialized, such as the page number or file path.
+        context: String,
+        source: serde_json::Error,
+    },
+
+    /// The config file is invalid, or is missing options or credentials.
+    #[error("invalid config")]
+    Config(#[from] ConfigError),
+
+    /// The archive (or cache) could not be read or written.
+    #[error("storage error at {}: {source}", path.display())]
+    Storage { path: PathBuf, source: StorageError },
+}
+
+impl PluckError {
+    pub(crate) fn deserialize(context: impl Into<String>, source: serde_json::Error) -> Self {
+        Self::Deserialize {
+            context: context.into(),
+            source,
+        }
+    }
+
+    pub(crate) fn storage(path: impl Into<PathBuf>, source: impl Into<StorageError>) -> Self {
+        Self::Storage {
+            path: path.into(),
+            source: source.into(),
+        }
+    }
+
+    /// Returns whether this error was caused by being rate limited.
+    pub fn is_rate_limit(&self) -> bool {
+        match self {
+            Self::HttpStatus { status, .. } => *status == reqwest::StatusCode::TOO_MANY_REQUESTS,
+            Self::LastfmApi { code, .. } => *code == 29,
+            Self::BlueskyApi { status, .. } => *status == 429,
+            Self::Twitter(egg_mode::error::Error::RateLimit(_)) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns whether this error was caused by missing or invalid credentials.
+    pub fn is_auth_failure(&self) -> bool {
+        match self {
+            Self::HttpStatus { status, .. } => {
+                *status == reqwest::StatusCode::UNAUTHORIZED
+                    || *status == reqwest::StatusCode::FORBIDDEN
+            }
+            Self::LastfmApi { code, .. } => matches!(code, 4 | 9 | 10 | 14 | 26),
+            Self::BlueskyApi { status, .. } => matches!(status, 401 | 403),
+            Self::Twitter(egg_mode::error::Error::BadStatus(status)) => {
+                status.as_u16() == 401 || status.as_u16() == 403
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns whether this error is transient, meaning the request may succeed if retried.
+    pub fn is_retryable(&self) -> bool {
+        if self.is_rate_limit() {
+            return true;
+        }
+
+        match self {
+            Self::Http(err) => err.is_timeout() || err.is_connect() || err.is_request(),
+            Self::HttpStatus { status, .. } => status.is_server_error(),
+            // 11: Service Offline, 16: The service is temporarily unavailable.
+            Self::LastfmApi { code, .. } => matches!(code, 11 | 16),
+            Self::BlueskyApi { status, .. } => *status >= 500,
+            Self::BlueskyHttp(_) => true,
+            Self::Twitter(egg_mode::error::Error::BadStatus(status)) => status.is_server_error(),
+            Self::Twitter(egg_mode::error::Error::NetError(_)) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns how long to wait before retrying, if the server told us.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Self::HttpStatus { retry_after, .. } | Self::BlueskyApi { retry_after, .. } => {
+                *retry_after
+            }
+            Self::Twitter(egg_mode::error::Error::RateLimit(reset_at)) => {
+                let reset_at = DateTime::from_timestamp(i64::from(*reset_at), 0)?;
+
+                (reset_at - Utc::now()).to_std().ok()
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<E: Debug + Display> From<xrpc::Error<E>> for PluckError {
+    fn from(err: xrpc::Error<E>) -> Self {
+        match err {
+            xrpc::Error::XrpcResponse(err) => Self::BlueskyApi {
+                status: err.status.as_u16(),
+                message: err.error.map(|err| err.to_string()).unwrap_or_default(),
+                retry_after: None,
+            },
+            xrpc::Error::HttpClient(err) => match err.downcast::<RetryAfterResponse>() {
+                Ok(response) => Self::BlueskyApi {
+                    status: response.status,
+                    message: response.message,
+                    retry_after: Some(response.retry_after),
+                },
+                Err(err) => Self::BlueskyHttp(err.to_string()),
+            },
+            err => Self::Bluesky(err.to_string()),
+        }
+    }
+}
+
+/// The underlying cause of a [`PluckError::Storage`] error.
+#[derive(Debug, Error)]
+pub enum StorageError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error("failed to parse TOML: {0}")]
+    TomlDeserialize(#[from] toml::de::Error),
+
+    #[error("failed to serialize TOML: {0}")]
+    TomlSerialize(#[from] toml::ser::Error),
+
+    #[error("failed to (de)serialize JSON: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("SQLite error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+
+    #[error("failed to read zip archive: {0}")]
+    Zip(#[from] zip::result::ZipError),
+
+    #[error("file name is not a valid partition")]
+    InvalidFileName,
+
+    #[error("invalid partition: {0}")]
+    InvalidPartition(String),
+
+    #[error("expected {expected} {collection}, but found {actual}")]
+    CountMismatch {
+        collection: &'static str,
+        expected: usize,
+        actual: usize,
+    },
+}
@@ -1,30 +1,73 @@
 mod types;
 
 use std::path::Path;
+use std::time::Duration;
 
+use async_trait::async_trait;
 use tokio::fs::File;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::AsyncReadExt;
+
+use crate::models;
+use crate::retry::{parse_retry_after, RetryPolicy};
+use crate::source::{Page, Source};
+use crate::storage::write_atomic;
+use crate::PluckError;
 
 pub use types::*;
 
 pub struct LastfmFetcher {
     user: String,
     api_key: String,
+    use_cache: bool,
+    include_metadata: bool,
+    retry_policy: RetryPolicy,
 }
 
 impl LastfmFetcher {
     pub fn new(user: String, api_key: String) -> Self {
-        Self { user, api_key }
+        Self {
+            user,
+            api_key,
+            use_cache: false,
+            include_metadata: false,
+            retry_policy: RetryPolicy::default(),
+        }
+    }
+
+    pub fn with_retry_policy(&mut self, retry_policy: RetryPolicy) -> &mut Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Serve pages from (and store fetched pages in) the on-disk page cache.
+    pub fn use_cache(&mut self) -> &mut Self {
+        self.use_cache = true;
+        self
+    }
+
+    /// Also archive each scrobble's MusicBrainz IDs, Last.fm URLs, album art and
+    /// whether the user has loved the track.
+    pub fn include_metadata(&mut self) -> &mut Self {
+        self.include_metadata = true;
+        self
     }
 
     pub async fn fetch_tracks_page_with_cache(
         &self,
         page: i32,
-    ) -> Result<GetRecentTracksResponse, Box<dyn std::error::Error>> {
-        let cache_dir = Path::new(".cache/lastfm");
+    ) -> Result<GetRecentTracksResponse, PluckError> {
+        // Each user's pages are cached separately, as accounts can be synced in parallel.
+        let mut cache_dir = Path::new(".cache/lastfm").join(&self.user);
+
+        // Pages fetched with `extended=1` have a different shape.
+        if self.include_metadata {
+            cache_dir.push("extended");
+        }
 
         if !cache_dir.exists() {
-            tokio::fs::create_dir_all(cache_dir).await?;
+            tokio::fs::create_dir_all(&cache_dir)
+                .await
+                .map_err(|err| PluckError::storage(&cache_dir, err))?;
         }
 
         let cached_page_path = cache_dir.join(format!("{}.json", page));
@@ -32,11 +75,18 @@ impl LastfmFetcher {
         if cached_page_path.exists() {
             println!("Fetching page {} from cache", page);
 
-            let mut cached_page = File::open(&cached_page_path).await?;
             let mut buffer = String::new();
-            cached_page.read_to_string(&mut buffer).await?;
+            File::open(&cached_page_path)
+                .await
+                .map_err(|err| PluckError::storage(&cached_page_path, err))?
+                .read_to_string(&mut buffer)
+                .await
+                .map_err(|err| PluckError::storage(&cached_page_path, err))?;
 
-            let response: GetRecentTracksResponse = serde_json::from_str(&buffer)?;
+            let response: GetRecentTracksResponse =
+                serde_json::from_str(&buffer).map_err(|err| {
+                    PluckError::deserialize(cached_page_path.display().to_string(), err)
+                })?;
 
             Ok(response)
         } else {
@@ -44,10 +94,12 @@ impl LastfmFetcher {
 
             let response = self.fetch_tracks_page(page).await?;
 
-            let mut cached_page = File::create(cached_page_path).await?;
-            cached_page
-                .write_all(serde_json::to_string_pretty(&response)?.as_bytes())
-                .await?;
+            let serialized_page = serde_json::to_string_pretty(&response)
+                .map_err(|err| PluckError::storage(&cached_page_path, err))?;
+
+            // Written atomically, so that an interrupted sync never leaves a partial
+            // page behind to be served from the cache.
+            write_atomic(&cached_page_path, serialized_page.as_bytes(), false).await?;
 
             Ok(response)
         }
@@ -56,12 +108,12 @@ impl LastfmFetcher {
     pub async fn fetch_tracks_page(
         &self,
         page: i32,
-    ) -> Result<GetRecentTracksResponse, Box<dyn std::error::Error>> {
+    ) -> Result<GetRecentTracksResponse, PluckError> {
         let query = {
             let limit = 200.to_string();
             let page = page.to_string();
 
-            let query_params: querystring::QueryParams = vec![
+            let mut query_params: querystring::QueryParams = vec![
                 ("user", &self.user),
                 ("api_key", &self.api_key),
                 ("method", "user.getrecenttracks"),
@@ -70,15 +122,223 @@ impl LastfmFetcher {
                 ("page", &page),
             ];
 
-            String::from(querystring::stringify(query_params).trim_end_matches("&"))
+            // Only extended responses say whether the user has loved each track.
+            if self.include_metadata {
+                query_params.push(("extended", "1"));
+            }
+
+            String::from(querystring::stringify(query_params).trim_end_matches('&'))
         };
 
         let url = format!("https://ws.audioscrobbler.com/2.0/?{}", query);
-        let response = reqwest::get(url)
-            .await?
-            .json::<GetRecentTracksResponse>()
-            .await?;
 
-        Ok(response)
+        self.retry_policy
+            .retry(|| Self::request_tracks_page(&url, page))
+            .await
+    }
+
+    async fn request_tracks_page(
+        url: &str,
+        page: i32,
+    ) -> Result<GetRecentTracksResponse, PluckError> {
+        let response = reqwest::get(url).await?;
+
+        let status = response.status();
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_retry_after);
+        let mut url = response.url().clone();
+        url.set_query(None);
+
+        let body = response.text().await?;
+
+        // Last.fm reports API errors with an error payload, which may accompany either
+        // a successful or an unsuccessful status code.
+        if let Ok(ErrorResponse { error, message }) = serde_json::from_str(&body) {
+            return Err(PluckError::LastfmApi {
+                code: error,
+                message,
+            });
+        }
+
+        if !status.is_success() {
+            return Err(PluckError::HttpStatus {
+                url: url.to_string(),
+                status,
+                retry_after,
+            });
+        }
+
+        serde_json::from_str(&body)
+            .map_err(|err| PluckError::deserialize(format!("Last.fm page {}", page), err))
+    }
+}
+
+#[async_trait]
+impl Source for LastfmFetcher {
+    type Item = models::Track;
+    type Cursor = i32;
+
+    async fn fetch_page(
+        &mut self,
+        cursor: Option<i32>,
+    ) -> Result<Page<models::Track, i32>, PluckError> {
+        let current_page = cursor.unwrap_or(1);
+
+        let response = if self.use_cache {
+            self.fetch_tracks_page_with_cache(current_page).await?
+        } else {
+            self.fetch_tracks_page(current_page).await?
+        };
+
+        let total_pages = response.recent_tracks.metadata.total_pages;
+
+        println!("Processing page {} of {}", current_page, total_pages);
+
+        let tracks = response
+            .recent_tracks
+            .track
+            .into_iter()
+            .filter_map(|track| match track {
+                PlayedOrNowPlayingTrack::Played(track) => Some(track),
+                PlayedOrNowPlayingTrack::NowPlaying(_) => None,
+            })
+            .map(|track| track_of_response(track, self.include_metadata))
+            .collect();
+
+        Ok(Page {
+            items: tracks,
+            next_cursor: (current_page < total_pages).then_some(current_page + 1),
+            newest_timestamp: None,
+        })
+    }
+
+    async fn pause(&self, pages_fetched: usize) {
+        if pages_fetched.is_multiple_of(10) {
+            println!("Taking a quick break...");
+
+            tokio::time::sleep(Duration::from_millis(1000)).await;
+        }
+    }
+}
+
+/// Converts a played track from the API into the [`models::Track`] we archive,
+/// with or without its metadata.
+fn track_of_response(track: Track, include_metadata: bool) -> models::Track {
+    if !include_metadata {
+        return models::Track {
+            name: track.name,
+            artist: track.artist.name,
+            album: track.album.name,
+            listened_at: track.date.timestamp,
+            mbid: None,
+            artist_mbid: None,
+            album_mbid: None,
+            url: None,
+            artist_url: None,
+            streamable: None,
+            loved: None,
+            images: None,
+        };
+    }
+
+    // Tracks without album art are given images with empty URLs.
+    let images = track.image.map(|images| {
+        images
+            .into_iter()
+            .filter(|image| !image.url.is_empty())
+            .map(|image| models::TrackImage {
+                size: image.size,
+                url: image.url,
+            })
+            .collect::<Vec<_>>()
+    });
+
+    models::Track {
+        name: track.name,
+        artist: track.artist.name,
+        album: track.album.name,
+        listened_at: track.date.timestamp,
+        mbid: track.mbid,
+        artist_mbid: track.artist.mbid,
+        album_mbid: track.album.mbid,
+        url: track.url,
+        artist_url: track.artist.url,
+        streamable: track.streamable.map(|streamable| streamable == "1"),
+        loved: track.loved.map(|loved| loved == "1"),
+        images: images.filter(|images| !images.is_empty()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXTENDED_TRACK: &str = r##"{
+        "artist": {
+            "url": "https://www.last.fm/music/Radiohead",
+            "name": "Radiohead",
+            "image": [{ "size": "small", "#text": "" }],
+            "mbid": "a74b1b7f-71a5-4011-9441-d0b5e4122711"
+        },
+        "mbid": "6b9a509f-6907-4a6e-9345-2f12da09ba4b",
+        "name": "Airbag",
+        "image": [
+            { "size": "small", "#text": "" },
+            { "size": "extralarge", "#text": "https://lastfm.freetls.fastly.net/i/u/300x300/x.png" }
+        ],
+        "url": "https://www.last.fm/music/Radiohead/_/Airbag",
+        "streamable": "0",
+        "album": { "mbid": "", "#text": "OK Computer" },
+        "loved": "1",
+        "date": { "uts": "1700000000", "#text": "14 Nov 2023, 22:13" }
+    }"##;
+
+    #[test]
+    fn tracks_keep_their_metadata_when_asked_to() {
+        let track = serde_json::from_str::<Track>(EXTENDED_TRACK).unwrap();
+        let track = track_of_response(track, true);
+
+        assert_eq!(track.artist, "Radiohead");
+        assert_eq!(track.listened_at.timestamp(), 1_700_000_000);
+        assert_eq!(
+            track.mbid.as_deref(),
+            Some("6b9a509f-6907-4a6e-9345-2f12da09ba4b")
+        );
+        assert_eq!(
+            track.artist_mbid.as_deref(),
+            Some("a74b1b7f-71a5-4011-9441-d0b5e4122711")
+        );
+        assert_eq!(track.album_mbid, None);
+        assert_eq!(
+            track.artist_url.as_deref(),
+            Some("https://www.last.fm/music/Radiohead")
+        );
+        assert_eq!(track.streamable, Some(false));
+        assert_eq!(track.loved, Some(true));
+        assert_eq!(
+            track.images,
+            Some(vec![models::TrackImage {
+                size: "extralarge".to_string(),
+                url: "https://lastfm.freetls.fastly.net/i/u/300x300/x.png".to_string(),
+            }])
+        );
+    }
+
+    #[test]
+    fn tracks_leave_out_their_metadata_otherwise() {
+        let track = serde_json::from_str::<Track>(EXTENDED_TRACK).unwrap();
+        let track = track_of_response(track, false);
+
+        assert_eq!(track.name, "Airbag");
+        assert_eq!(track.album, "OK Computer");
+        assert_eq!(track.mbid, None);
+        assert_eq!(track.loved, None);
+        assert_eq!(track.images, None);
+
+        let toml = toml::to_string(&track).unwrap();
+        assert_eq!(toml.lines().count(), 4, "{}", toml);
     }
 }
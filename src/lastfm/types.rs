@@ -1,6 +1,6 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use serde_with::{serde_as, DisplayFromStr, TimestampSeconds};
+use serde_with::{serde_as, DisplayFromStr, NoneAsEmptyString, TimestampSeconds};
 
 /// The response from the [`user.getRecentTracks`](https://www.last.fm/api/show/user.getRecentTracks) method.
 #[derive(Debug, Serialize, Deserialize)]
@@ -9,6 +9,15 @@ pub struct GetRecentTracksResponse {
     pub recent_tracks: RecentTracks,
 }
 
+/// The payload returned when a Last.fm API call fails.
+///
+/// See [Error Codes](https://www.last.fm/api/errorcodes) for the list of possible errors.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    pub error: i32,
+    pub message: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RecentTracks {
     pub track: Vec<PlayedOrNowPlayingTrack>,
@@ -31,24 +40,66 @@ pub struct NowPlayingTrack {
     pub album: Album,
 }
 
+/// A played track.
+///
+/// Tracks fetched with `extended=1` also say whether the user has loved them.
+#[serde_as]
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Track {
     pub name: String,
     pub artist: Artist,
     pub album: Album,
     pub date: TrackDate,
+
+    /// The track's MusicBrainz ID, which Last.fm leaves empty when it isn't known.
+    #[serde_as(as = "NoneAsEmptyString")]
+    #[serde(default)]
+    pub mbid: Option<String>,
+
+    pub url: Option<String>,
+    pub image: Option<Vec<Image>>,
+
+    /// Either `0` or `1`.
+    pub streamable: Option<String>,
+
+    /// Either `0` or `1`.
+    pub loved: Option<String>,
 }
 
+/// The artist of a track, whose name is given as `#text`, or as `name` for
+/// tracks fetched with `extended=1`.
+#[serde_as]
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Artist {
-    #[serde(rename = "#text")]
+    #[serde(rename = "#text", alias = "name")]
     pub name: String,
+
+    #[serde_as(as = "NoneAsEmptyString")]
+    #[serde(default)]
+    pub mbid: Option<String>,
+
+    /// The artist's page on Last.fm, which is only given with `extended=1`.
+    pub url: Option<String>,
 }
 
+#[serde_as]
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Album {
     #[serde(rename = "#text")]
     pub name: String,
+
+    #[serde_as(as = "NoneAsEmptyString")]
+    #[serde(default)]
+    pub mbid: Option<String>,
+}
+
+/// A copy of an album cover, e.g. the `extralarge` one.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Image {
+    pub size: String,
+
+    #[serde(rename = "#text")]
+    pub url: String,
 }
 
 #[serde_as]
@@ -0,0 +1,15 @@
+pub mod bluesky;
+pub mod checkpoint;
+pub mod config;
+pub mod convert;
+mod error;
+pub mod lastfm;
+pub mod media;
+pub mod models;
+pub mod retry;
+pub mod source;
+pub mod storage;
+pub mod sync;
+pub mod twitter;
+
+pub use error::*;
@@ -1,639 +1,1231 @@
-mod bluesky;
-mod lastfm;
-mod twitter;
-
-use std::cmp::Ordering;
-use std::collections::HashMap;
 use std::env;
-use std::ffi::OsStr;
+use std::future::Future;
 use std::path::{Path, PathBuf};
-use std::time::Duration;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use bluesky::{BlueskyFetcher, FetchPostsOutput};
-use chrono::{DateTime, Datelike, Utc};
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use dotenv::dotenv;
-use indexmap::set::IndexSet;
-use serde::{Deserialize, Serialize};
-use tokio::fs::File;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use twitter::TwitterArchiveImporter;
+use pluck::bluesky::{
+    session_path, BlueskyFetcher, BlueskyLikes, BlueskyRepo, BlueskyRepoImporter, BlueskyReposts,
+    BlueskySessionStore, BlueskyThreads, REPO_CAR_FILE_NAME,
+};
+use pluck::config::{
+    BlueskyAccount, Config, ConfigError, Credential, LastfmAccount, RateLimitConfig, SourceConfig,
+    TwitterAccount,
+};
+use pluck::convert::{convert, copy_archive_files};
+use pluck::lastfm::LastfmFetcher;
+use pluck::media::MediaStore;
+use pluck::models::{
+    BlueskyBlock, BlueskyFollow, BlueskyLike, BlueskyPost, BlueskyRepost, BlueskyThread,
+    DirectMessage, Follower, Following, Like, Record, Track, Tweet, TwitterProfile,
+};
+use pluck::retry::RetryPolicy;
+use pluck::source::Source;
+use pluck::storage::{write_atomic, AnyStorage, Partitioning, Storage, StorageFormat};
+use pluck::sync::{is_full_sync, sync, SyncOptions, SyncSummary};
+use pluck::twitter::{
+    ArchivedDmConversationWrapper, ArchivedFollowerWrapper, ArchivedFollowingWrapper,
+    ArchivedLikeWrapper, TwitterArchive, TwitterArchiveDataImporter, TwitterArchiveImporter,
+    TwitterProfileImporter, TwitterTimelineFetcher,
+};
+use pluck::PluckError;
+use tokio::task::JoinHandle;
+
+/// Archives your data from Last.fm, Bluesky and Twitter into files you own.
+#[derive(Debug, Parser)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    #[clap(subcommand)]
+    command: Command,
 
-use crate::lastfm::{LastfmFetcher, PlayedOrNowPlayingTrack};
+    /// The config file to read. Defaults to `pluck.toml`, if it exists.
+    #[clap(long, global = true)]
+    config: Option<PathBuf>,
 
-#[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
-struct BlueskyPost {
-    pub uri: String,
-    pub created_at: DateTime<Utc>,
-    pub text: String,
-    pub in_reply_to: Option<BlueskyPostReply>,
+    #[clap(flatten)]
+    rate_limit: RateLimitArgs,
 }
 
-#[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
-struct BlueskyPostReply {
-    pub uri: String,
-    pub author_did: String,
-    pub author_handle: String,
-}
+/// Overrides for the `rate_limit` options in the config file.
+#[derive(Debug, clap::Args)]
+struct RateLimitArgs {
+    /// The maximum number of attempts for each request. Set to 1 to disable retries. [default: 5]
+    #[clap(long, global = true)]
+    max_attempts: Option<u32>,
 
-#[derive(Debug, Serialize, Deserialize)]
-struct BlueskyYearData {
-    posts: IndexSet<BlueskyPost>,
-}
+    /// The delay (in milliseconds) before the first retry, doubling with each retry. [default: 1000]
+    #[clap(long, global = true)]
+    initial_backoff_ms: Option<u64>,
 
-#[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
-struct Track {
-    pub name: String,
-    pub artist: String,
-    pub album: String,
-    pub listened_at: DateTime<Utc>,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct YearData {
-    tracks: IndexSet<Track>,
-}
+    /// The maximum delay (in milliseconds) between retries. [default: 60000]
+    #[clap(long, global = true)]
+    max_backoff_ms: Option<u64>,
 
-#[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
-struct Tweet {
-    pub id: u64,
-    pub created_at: DateTime<Utc>,
-    pub text: String,
-    pub entities: Option<TweetEntities>,
-    pub in_reply_to: Option<TweetReply>,
+    /// The delay (in milliseconds) between pages, in place of each source's own pacing.
+    #[clap(long, global = true)]
+    page_delay_ms: Option<u64>,
 }
 
-impl From<egg_mode::tweet::Tweet> for Tweet {
-    fn from(tweet: egg_mode::tweet::Tweet) -> Self {
-        Self {
-            id: tweet.id,
-            text: tweet.text,
-            entities: TweetEntities {
-                urls: {
-                    let urls = tweet
-                        .entities
-                        .urls
-                        .into_iter()
-                        .map(|entity| TweetUrlEntity {
-                            display_url: entity.display_url,
-                            expanded_url: entity.expanded_url,
-                            url: entity.url,
-                        })
-                        .collect::<Vec<_>>();
-
-                    if urls.is_empty() {
-                        None
-                    } else {
-                        Some(urls)
-                    }
-                },
-                media: tweet.entities.media.map(|media| {
-                    media
-                        .into_iter()
-                        .map(|entity| TweetMediaEntity {
-                            id: entity.id,
-                            r#type: entity.media_type.into(),
-                            url: entity.media_url_https,
-                        })
-                        .collect()
-                }),
-            }
-            .into_option(),
-            in_reply_to: match (
-                tweet.in_reply_to_status_id,
-                tweet.in_reply_to_user_id,
-                tweet.in_reply_to_screen_name,
-            ) {
-                (Some(status_id), Some(user_id), Some(user_name)) => Some(TweetReply {
-                    status_id,
-                    user_id,
-                    user_name,
-                }),
-                _ => None,
-            },
-            created_at: tweet.created_at,
+impl RateLimitArgs {
+    /// Returns the given rate limit config with these arguments applied on top.
+    fn apply(&self, config: &RateLimitConfig) -> RateLimitConfig {
+        RateLimitConfig {
+            page_delay_ms: self.page_delay_ms.or(config.page_delay_ms),
+            max_attempts: self.max_attempts.or(config.max_attempts),
+            initial_backoff_ms: self.initial_backoff_ms.or(config.initial_backoff_ms),
+            max_backoff_ms: self.max_backoff_ms.or(config.max_backoff_ms),
         }
     }
 }
 
-impl From<twitter::ArchivedTweet> for Tweet {
-    fn from(tweet: twitter::ArchivedTweet) -> Self {
-        Self {
-            id: tweet.id,
-            text: tweet.full_text,
-            entities: TweetEntities {
-                urls: {
-                    let urls = tweet
-                        .entities
-                        .urls
-                        .into_iter()
-                        .filter_map(|entity| match entity {
-                            twitter::ArchivedTweetUrlEntity::WellFormed(entity) => {
-                                Some(TweetUrlEntity {
-                                    display_url: entity.display_url,
-                                    expanded_url: entity.expanded_url,
-                                    url: entity.url,
-                                })
-                            }
-                            twitter::ArchivedTweetUrlEntity::Malformed { .. } => None,
-                        })
-                        .collect::<Vec<_>>();
-
-                    if urls.is_empty() {
-                        None
-                    } else {
-                        Some(urls)
-                    }
-                },
-                media: tweet.entities.media.map(|media| {
-                    media
-                        .into_iter()
-                        .map(|entity| TweetMediaEntity {
-                            id: entity.id,
-                            r#type: entity.media_type.into(),
-                            url: entity.media_url_https,
-                        })
-                        .collect()
-                }),
-            }
-            .into_option(),
-            in_reply_to: match (
-                tweet.in_reply_to_status_id,
-                tweet.in_reply_to_user_id,
-                tweet.in_reply_to_screen_name,
-            ) {
-                (Some(status_id), Some(user_id), Some(user_name)) => Some(TweetReply {
-                    status_id,
-                    user_id,
-                    user_name,
-                }),
-                _ => None,
-            },
-            created_at: tweet.created_at,
-        }
-    }
+/// The options for syncing a single source.
+#[derive(Debug, clap::Args)]
+struct SourceArgs {
+    /// The directory to store the archive in. Overrides `output_dir` in the config file.
+    output_dir: Option<PathBuf>,
+
+    /// The name of the configured account to sync, if the source has more than one.
+    #[clap(long)]
+    account: Option<String>,
+
+    #[clap(flatten)]
+    sync: SyncArgs,
 }
 
-#[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
-struct TweetEntities {
-    pub urls: Option<Vec<TweetUrlEntity>>,
-    pub media: Option<Vec<TweetMediaEntity>>,
+/// The options for logging in to the Bluesky account to sync.
+#[derive(Debug, Default, clap::Args)]
+struct BlueskyLoginArgs {
+    /// The handle to archive. Overrides `handle` in the config file.
+    ///
+    /// Without a login, this can also be the account's DID.
+    #[clap(long)]
+    handle: Option<String>,
+
+    /// Archive the account without logging in, from the public AppView.
+    ///
+    /// Posts, reposts, threads and the repository can be archived this way, but
+    /// likes can't, as Bluesky only shows them to the account itself.
+    #[clap(long, action)]
+    public: bool,
+
+    /// The URL of the PDS to log in to, instead of the one the handle resolves
+    /// to. Overrides `pds` in the config file.
+    #[clap(long)]
+    pds: Option<String>,
 }
 
-impl TweetEntities {
-    pub fn is_empty(&self) -> bool {
-        self.urls.is_none() && self.media.is_none()
-    }
+/// The options shared by every sync.
+#[derive(Debug, Clone, clap::Args)]
+struct SyncArgs {
+    #[clap(short, long, action)]
+    full_sync: bool,
 
-    pub fn into_option(self) -> Option<TweetEntities> {
-        if self.is_empty() {
-            None
-        } else {
-            Some(self)
-        }
-    }
-}
+    /// Continue an interrupted sync from its last checkpoint.
+    #[clap(long, action)]
+    resume: bool,
 
-#[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
-struct TweetUrlEntity {
-    pub display_url: String,
-    pub expanded_url: Option<String>,
-    pub url: String,
-}
+    /// Keep the previous version of each rewritten file as a `.bak` file.
+    #[clap(long, action)]
+    backup: bool,
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
-pub enum MediaType {
-    #[serde(rename = "photo")]
-    Photo,
+    /// How many days before the newest archived item to re-check for missed items.
+    #[clap(long, default_value_t = 1)]
+    overlap_days: i64,
 
-    #[serde(rename = "video")]
-    Video,
+    /// The format to store the archive in. [default: toml]
+    #[clap(long, value_enum)]
+    format: Option<Format>,
 
-    #[serde(rename = "animated_gif")]
-    Gif,
-}
+    /// How to split up the archive. [default: year]
+    #[clap(long, value_enum)]
+    partition_by: Option<PartitionBy>,
 
-impl From<egg_mode::entities::MediaType> for MediaType {
-    fn from(value: egg_mode::entities::MediaType) -> Self {
-        match value {
-            egg_mode::entities::MediaType::Photo => MediaType::Photo,
-            egg_mode::entities::MediaType::Video => MediaType::Video,
-            egg_mode::entities::MediaType::Gif => MediaType::Gif,
-        }
-    }
+    /// Download the photos, videos and GIFs attached to tweets and posts into
+    /// the archive's `media` directory.
+    #[clap(long, action)]
+    download_media: bool,
 }
 
-impl From<twitter::MediaType> for MediaType {
-    fn from(value: twitter::MediaType) -> Self {
-        match value {
-            twitter::MediaType::Photo => MediaType::Photo,
-            twitter::MediaType::Video => MediaType::Video,
-            twitter::MediaType::Gif => MediaType::Gif,
-        }
-    }
-}
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// One TOML file per partition.
+    Toml,
+
+    /// One JSON file per partition.
+    Json,
+
+    /// One JSON Lines file per partition.
+    #[clap(name = "jsonl")]
+    JsonLines,
 
-#[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
-struct TweetMediaEntity {
-    pub id: u64,
-    pub r#type: MediaType,
-    pub url: String,
+    /// A single SQLite database (`pluck.sqlite`).
+    Sqlite,
 }
 
-#[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
-struct TweetReply {
-    pub status_id: u64,
-    pub user_id: u64,
-    pub user_name: String,
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum PartitionBy {
+    /// One partition per year, e.g. `2024.toml`.
+    Year,
+
+    /// One partition per month, e.g. `2024/03.toml`.
+    Month,
+
+    /// One partition per day, e.g. `2024/03/15.toml`.
+    Day,
+
+    /// A single partition, e.g. `tracks.toml`.
+    Single,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct TwitterYearData {
-    tweets: IndexSet<Tweet>,
+impl From<Format> for StorageFormat {
+    fn from(format: Format) -> Self {
+        match format {
+            Format::Toml => Self::Toml,
+            Format::Json => Self::Json,
+            Format::JsonLines => Self::Jsonl,
+            Format::Sqlite => Self::Sqlite,
+        }
+    }
 }
 
-#[derive(Debug, Parser)]
-#[clap(author, version, about, long_about = None)]
-struct Args {
-    #[clap(subcommand)]
-    command: Command,
+impl From<PartitionBy> for Partitioning {
+    fn from(partition_by: PartitionBy) -> Self {
+        match partition_by {
+            PartitionBy::Year => Self::Year,
+            PartitionBy::Month => Self::Month,
+            PartitionBy::Day => Self::Day,
+            PartitionBy::Single => Self::Single,
+        }
+    }
 }
 
 #[derive(Debug, Subcommand)]
 enum Command {
+    /// Syncs every configured source and account at once.
+    Sync {
+        #[clap(flatten)]
+        sync: SyncArgs,
+    },
+    /// Syncs a Bluesky account's posts into the output directory.
     Bluesky {
-        output_dir: PathBuf,
+        #[clap(subcommand)]
+        command: Option<BlueskyCommand>,
+
+        #[clap(flatten)]
+        source: SourceArgs,
 
-        #[clap(short, long, action)]
-        full_sync: bool,
+        #[clap(flatten)]
+        login: BlueskyLoginArgs,
+
+        /// Also archive reposts, into the `reposts` subdirectory of the output directory.
+        #[clap(long, action)]
+        include_reposts: bool,
+
+        /// Also archive every reply to the account's own threads, into the
+        /// `threads` subdirectory of the output directory.
+        #[clap(long, action)]
+        with_threads: bool,
     },
+    /// Syncs a Last.fm user's scrobbles into the output directory.
     Lastfm {
-        output_dir: PathBuf,
+        #[clap(flatten)]
+        source: SourceArgs,
 
-        #[clap(short, long, action)]
-        full_sync: bool,
+        /// The user to archive. Overrides `user` in the config file.
+        #[clap(long)]
+        user: Option<String>,
+
+        /// Also archive each scrobble's MusicBrainz IDs, Last.fm URLs, album art
+        /// and loved flag.
+        #[clap(long, action)]
+        include_metadata: bool,
     },
+    /// Syncs a Twitter account's tweets into the output directory.
     Twitter {
-        output_dir: PathBuf,
+        #[clap(flatten)]
+        source: SourceArgs,
+
+        /// The screen name to archive. Overrides `screen_name` in the config file.
+        #[clap(long)]
+        screen_name: Option<String>,
+
+        /// Import from a Twitter archive (the downloaded `.zip` or the
+        /// directory it was extracted to) instead of the API.
+        ///
+        /// Along with tweets, the archive's likes, DMs, followers, following and
+        /// profile are imported into subdirectories of the output directory.
+        #[clap(long)]
+        archive: Option<PathBuf>,
+    },
+    /// Converts an archive from one storage format to another.
+    Convert {
+        /// The format of the existing archive.
+        #[clap(long, value_enum)]
+        from: Format,
 
-        #[clap(short, long, action)]
-        full_sync: bool,
+        /// The format to convert the archive to.
+        #[clap(long, value_enum)]
+        to: Format,
 
-        #[clap(long, action)]
-        from_archive: bool,
+        /// How the existing archive is split up.
+        #[clap(long, value_enum, default_value_t = PartitionBy::Year)]
+        from_partition_by: PartitionBy,
+
+        /// How to split up the converted archive.
+        #[clap(long, value_enum, default_value_t = PartitionBy::Year)]
+        to_partition_by: PartitionBy,
+
+        source_dir: PathBuf,
+
+        target_dir: PathBuf,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum BlueskyCommand {
+    /// Syncs only the posts the account has liked, into the `likes`
+    /// subdirectory of the output directory.
+    Likes {
+        #[clap(flatten)]
+        source: SourceArgs,
+
+        #[clap(flatten)]
+        login: BlueskyLoginArgs,
+    },
+    /// Downloads the account's whole repository and imports every post, like,
+    /// repost, follow and block in it.
+    ///
+    /// Posts are stored in the output directory, and everything else in
+    /// subdirectories of it. The downloaded repository is kept in `repo.car`.
+    Repo {
+        #[clap(flatten)]
+        source: SourceArgs,
+
+        #[clap(flatten)]
+        login: BlueskyLoginArgs,
+
+        /// Import from a repository that has already been downloaded (a `.car`
+        /// file) instead of downloading it.
+        #[clap(long)]
+        car: Option<PathBuf>,
     },
 }
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+async fn main() -> ExitCode {
     dotenv().ok();
 
     let args = Args::parse();
 
-    match args.command {
-        Command::Bluesky {
-            output_dir,
-            full_sync,
-        } => {
-            let bluesky_handle = env::var("BLUESKY_HANDLE")?;
-            let bluesky_app_password = env::var("BLUESKY_APP_PASSWORD")?;
+    match run(args).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {:#}", err);
 
-            let mut posts_by_year: HashMap<i32, IndexSet<BlueskyPost>> = HashMap::new();
+            exit_code(&err)
+        }
+    }
+}
 
-            let latest_year_data = if !full_sync {
-                get_latest_bluesky_year_data(&output_dir).await?
-            } else {
-                None
-            };
+/// Maps an error to the exit code reported by the CLI.
+///
+/// - `1`: Any other error
+/// - `2`: The command-line arguments are invalid (reported by clap itself)
+/// - `3`: Missing or invalid credentials
+/// - `4`: Rate limited
+/// - `5`: The API returned an error
+/// - `6`: A response or file could not be deserialized
+/// - `7`: The archive could not be read or written
+/// - `8`: The config file is invalid, or is missing options or credentials
+fn exit_code(err: &anyhow::Error) -> ExitCode {
+    let Some(err) = err.downcast_ref::<PluckError>() else {
+        return ExitCode::FAILURE;
+    };
+
+    if err.is_auth_failure() {
+        return ExitCode::from(3);
+    }
 
-            if let Some((latest_year, latest_year_data)) = latest_year_data {
-                posts_by_year.insert(latest_year, latest_year_data.posts);
-            }
+    if err.is_rate_limit() {
+        return ExitCode::from(4);
+    }
 
-            let mut bluesky_fetcher = BlueskyFetcher::new(bluesky_handle, bluesky_app_password);
+    match err {
+        PluckError::Http(_)
+        | PluckError::HttpStatus { .. }
+        | PluckError::LastfmApi { .. }
+        | PluckError::BlueskyApi { .. }
+        | PluckError::BlueskyHttp(_)
+        | PluckError::Bluesky(_)
+        | PluckError::Twitter(_) => ExitCode::from(5),
+        PluckError::Deserialize { .. } | PluckError::InvalidBlueskyRepo(_) => ExitCode::from(6),
+        PluckError::Storage { .. } => ExitCode::from(7),
+        PluckError::Config(_) => ExitCode::from(8),
+    }
+}
 
-            let FetchPostsOutput {
-                posts: mut fetched_posts,
-                mut cursor,
-            } = bluesky_fetcher.fetch_posts(None).await?;
+/// Where and how to sync a single account.
+struct SyncTarget {
+    output_dir: PathBuf,
+    format: StorageFormat,
+    keep_backups: bool,
+    download_media: bool,
+    storage: AnyStorage,
+    options: SyncOptions,
+    retry_policy: RetryPolicy,
+}
 
-            'fetch_posts: loop {
-                for post in fetched_posts {
-                    let year = post.created_at.year();
-                    let is_new_post = posts_by_year
-                        .entry(year)
-                        .or_insert(IndexSet::new())
-                        .insert(post);
+impl SyncTarget {
+    /// Resolves the target for an account, using the command-line arguments in
+    /// place of the source's config.
+    fn new<A>(
+        source_name: &'static str,
+        config: &SourceConfig<A>,
+        account_name: Option<&str>,
+        output_dir: Option<PathBuf>,
+        args: &SyncArgs,
+        rate_limit_args: &RateLimitArgs,
+    ) -> Result<Self, PluckError> {
+        let output_dir = output_dir
+            .or_else(|| config.output_dir(account_name))
+            .ok_or(ConfigError::Missing {
+                source_name,
+                option: "output_dir",
+            })?;
+
+        let format = args
+            .format
+            .map(StorageFormat::from)
+            .or(config.format)
+            .unwrap_or_default();
+        let partitioning = args
+            .partition_by
+            .map(Partitioning::from)
+            .or(config.partition_by)
+            .unwrap_or_default();
+
+        let storage = open_storage(&output_dir, format, partitioning, args.backup)?;
+
+        let rate_limit = rate_limit_args.apply(&config.rate_limit);
+
+        let options = SyncOptions {
+            full_sync: args.full_sync,
+            resume: args.resume,
+            overlap: chrono::Duration::days(args.overlap_days),
+            page_delay: rate_limit.page_delay(),
+            ..SyncOptions::default()
+        };
+
+        Ok(Self {
+            output_dir,
+            format,
+            keep_backups: args.backup,
+            download_media: args.download_media,
+            storage,
+            options,
+            retry_policy: rate_limit.retry_policy(),
+        })
+    }
 
-                    if !is_new_post {
-                        break 'fetch_posts;
-                    }
-                }
+    /// Returns a target for storing a different kind of data in a subdirectory
+    /// of this target's output directory, optionally partitioned differently.
+    fn subdirectory(
+        &self,
+        name: &str,
+        partitioning: Option<Partitioning>,
+    ) -> Result<Self, PluckError> {
+        let output_dir = self.output_dir.join(name);
+        let partitioning = partitioning.unwrap_or_else(|| self.storage.partitioning());
+
+        Ok(Self {
+            storage: open_storage(&output_dir, self.format, partitioning, self.keep_backups)?,
+            output_dir,
+            format: self.format,
+            keep_backups: self.keep_backups,
+            download_media: self.download_media,
+            options: self.options.clone(),
+            retry_policy: self.retry_policy.clone(),
+        })
+    }
 
-                FetchPostsOutput {
-                    posts: fetched_posts,
-                    cursor,
-                } = bluesky_fetcher.fetch_posts(cursor).await?;
+    /// Returns the store to download media into, if media should be downloaded.
+    fn media_store(&self) -> Option<MediaStore> {
+        if !self.download_media {
+            return None;
+        }
 
-                tokio::time::sleep(Duration::from_millis(1000)).await;
-            }
+        let mut media_store = MediaStore::new(&self.output_dir);
+        media_store.with_retry_policy(self.retry_policy.clone());
 
-            for (year, mut posts) in posts_by_year {
-                posts.sort_unstable_by(|a, b| b.uri.cmp(&a.uri));
+        Some(media_store)
+    }
 
-                let mut file = File::create(output_dir.join(format!("{}.toml", year))).await?;
-                file.write_all(toml::to_string_pretty(&BlueskyYearData { posts })?.as_bytes())
-                    .await?;
-            }
-        }
-        Command::Lastfm {
-            output_dir,
-            full_sync,
-        } => {
-            let lastfm_user = env::var("LASTFM_USER")?;
-            let lastfm_api_key = env::var("LASTFM_API_KEY")?;
+    async fn sync<S: Source>(&self, source: &mut S) -> Result<SyncSummary, PluckError> {
+        sync(source, &self.storage, &self.options).await
+    }
+}
 
-            let mut tracks_by_year: HashMap<i32, IndexSet<Track>> = HashMap::new();
+/// Creates the output directory, if needed, and opens the storage within it.
+fn open_storage(
+    output_dir: &Path,
+    format: StorageFormat,
+    partitioning: Partitioning,
+    keep_backups: bool,
+) -> Result<AnyStorage, PluckError> {
+    std::fs::create_dir_all(output_dir).map_err(|err| PluckError::Storage {
+        path: output_dir.to_owned(),
+        source: err.into(),
+    })?;
+
+    format.open(output_dir, partitioning, keep_backups)
+}
 
-            let mut current_page = 1;
+/// What to archive from a Bluesky account.
+#[derive(Debug, Clone, Copy)]
+struct BlueskyCollections {
+    posts: bool,
+    reposts: bool,
+    likes: bool,
+    threads: bool,
+}
 
-            let latest_year_data = if !full_sync {
-                get_latest_year_data(&output_dir).await?
-            } else {
-                None
-            };
+impl BlueskyCollections {
+    /// Returns the collections to archive for the given account, with reposts
+    /// and threads included if either the command line or the config asks for them.
+    fn for_account(account: &BlueskyAccount, include_reposts: bool, with_threads: bool) -> Self {
+        Self {
+            posts: true,
+            reposts: include_reposts || account.include_reposts.unwrap_or(false),
+            likes: account.include_likes.unwrap_or(false),
+            threads: with_threads || account.include_threads.unwrap_or(false),
+        }
+    }
+}
 
-            if let Some((latest_year, latest_year_data)) = latest_year_data {
-                tracks_by_year.insert(latest_year, latest_year_data.tracks);
-            }
+fn bluesky_fetcher(
+    account: &BlueskyAccount,
+    login: BlueskyLoginArgs,
+    target: &SyncTarget,
+) -> Result<BlueskyFetcher, PluckError> {
+    let bluesky_handle = resolve_option(
+        "bluesky",
+        "handle",
+        login.handle,
+        account.handle.clone(),
+        "BLUESKY_HANDLE",
+    )?;
+
+    let mut bluesky_fetcher = if login.public || account.public.unwrap_or(false) {
+        BlueskyFetcher::public(bluesky_handle)
+    } else {
+        let bluesky_app_password = account
+            .app_password
+            .clone()
+            .unwrap_or_else(|| Credential::env("BLUESKY_APP_PASSWORD"))
+            .resolve()?;
 
-            let lastfm_fetcher = LastfmFetcher::new(lastfm_user, lastfm_api_key);
+        let session_store = BlueskySessionStore::file(session_path(&bluesky_handle));
 
-            let response = if full_sync {
-                lastfm_fetcher
-                    .fetch_tracks_page_with_cache(current_page)
-                    .await?
-            } else {
-                lastfm_fetcher.fetch_tracks_page(current_page).await?
-            };
+        let mut bluesky_fetcher = BlueskyFetcher::new(bluesky_handle, bluesky_app_password);
+        bluesky_fetcher.with_session_store(session_store);
+        bluesky_fetcher
+    };
 
-            let total_pages = response.recent_tracks.metadata.total_pages;
+    bluesky_fetcher.with_retry_policy(target.retry_policy.clone());
 
-            'fetch_tracks: loop {
-                println!("Processing page {} of {}", current_page, total_pages);
+    if let Some(pds) = login.pds.or_else(|| account.pds.clone()) {
+        bluesky_fetcher.with_pds(pds);
+    }
 
-                let response = if full_sync {
-                    lastfm_fetcher
-                        .fetch_tracks_page_with_cache(current_page)
-                        .await?
-                } else {
-                    lastfm_fetcher.fetch_tracks_page(current_page).await?
-                };
+    if let Some(media_store) = target.media_store() {
+        bluesky_fetcher.with_media_store(media_store);
+    }
 
-                for track in
-                    response
-                        .recent_tracks
-                        .track
-                        .into_iter()
-                        .filter_map(|track| match track {
-                            PlayedOrNowPlayingTrack::Played(track) => Some(track),
-                            PlayedOrNowPlayingTrack::NowPlaying(_) => None,
-                        })
-                {
-                    let track = Track {
-                        name: track.name,
-                        artist: track.artist.name,
-                        album: track.album.name,
-                        listened_at: track.date.timestamp,
-                    };
+    Ok(bluesky_fetcher)
+}
 
-                    let year = track.listened_at.year();
-                    let is_new_track = tracks_by_year
-                        .entry(year)
-                        .or_insert(IndexSet::new())
-                        .insert(track);
+async fn sync_bluesky(
+    account: &BlueskyAccount,
+    login: BlueskyLoginArgs,
+    collections: BlueskyCollections,
+    target: &SyncTarget,
+) -> Result<SyncSummary, PluckError> {
+    let mut bluesky_fetcher = bluesky_fetcher(account, login, target)?;
 
-                    if !is_new_track {
-                        break 'fetch_tracks;
-                    }
-                }
+    let mut summary = SyncSummary::default();
 
-                current_page += 1;
+    if collections.posts {
+        summary += target.sync(&mut bluesky_fetcher).await?;
+    }
 
-                if current_page > total_pages {
-                    break;
-                }
+    if collections.reposts {
+        summary += target
+            .subdirectory("reposts", None)?
+            .sync(&mut BlueskyReposts(&mut bluesky_fetcher))
+            .await?;
+    }
 
-                if current_page % 10 == 0 {
-                    println!("Taking a quick break...");
+    if collections.likes {
+        summary += target
+            .subdirectory("likes", None)?
+            .sync(&mut BlueskyLikes(&mut bluesky_fetcher))
+            .await?;
+    }
 
-                    tokio::time::sleep(Duration::from_millis(1000)).await;
-                }
-            }
+    if collections.threads {
+        let target = target.subdirectory("threads", None)?;
+        let mut threads = BlueskyThreads::new(&mut bluesky_fetcher);
 
-            for (year, mut tracks) in tracks_by_year {
-                tracks.sort_unstable_by(|a, b| match b.listened_at.cmp(&a.listened_at) {
-                    Ordering::Equal => b.name.cmp(&a.name),
-                    ord => ord,
-                });
+        // Full syncs fetch every thread again, which picks up the replies
+        // further down threads whose root post's reply count hasn't changed.
+        if !is_full_sync::<BlueskyThread>(&target.storage, &target.options).await? {
+            threads.skip_unchanged(&target.storage).await?;
+        }
 
-                let mut file = File::create(output_dir.join(format!("{}.toml", year))).await?;
-                file.write_all(toml::to_string_pretty(&YearData { tracks })?.as_bytes())
-                    .await?;
-            }
+        summary += target.sync(&mut threads).await?;
+    }
+
+    Ok(summary)
+}
+
+/// Imports everything in a Bluesky account's repository, downloading it unless
+/// a CAR file is given.
+async fn import_bluesky_repo(
+    account: &BlueskyAccount,
+    login: BlueskyLoginArgs,
+    car: Option<PathBuf>,
+    target: &SyncTarget,
+) -> Result<SyncSummary, PluckError> {
+    let repo = match car {
+        Some(car) => BlueskyRepo::open(&car)?,
+        None => {
+            let car = bluesky_fetcher(account, login, target)?
+                .fetch_repo()
+                .await?;
+
+            write_atomic(
+                &target.output_dir.join(REPO_CAR_FILE_NAME),
+                &car,
+                target.keep_backups,
+            )
+            .await?;
+
+            BlueskyRepo::from_car(&car)?
         }
-        Command::Twitter {
-            output_dir,
-            full_sync,
-            from_archive,
-        } => {
-            let mut tweets_by_year: HashMap<i32, IndexSet<Tweet>> = HashMap::new();
+    };
 
-            let latest_year_data = if !full_sync {
-                get_latest_twitter_year_data(&output_dir).await?
-            } else {
-                None
-            };
+    let repo = Arc::new(repo);
 
-            if let Some((latest_year, latest_year_data)) = latest_year_data {
-                tweets_by_year.insert(latest_year, latest_year_data.tweets);
-            }
+    let mut summary = target
+        .sync(&mut BlueskyRepoImporter::<BlueskyPost>::new(repo.clone()))
+        .await?;
 
-            if !from_archive {
-                let twitter_consumer_key = env::var("TWITTER_CONSUMER_KEY")?;
-                let twitter_consumer_secret = env::var("TWITTER_CONSUMER_SECRET")?;
+    summary += target
+        .subdirectory("likes", None)?
+        .sync(&mut BlueskyRepoImporter::<BlueskyLike>::new(repo.clone()))
+        .await?;
 
-                let consumer_token =
-                    egg_mode::KeyPair::new(twitter_consumer_key, twitter_consumer_secret);
+    summary += target
+        .subdirectory("reposts", None)?
+        .sync(&mut BlueskyRepoImporter::<BlueskyRepost>::new(repo.clone()))
+        .await?;
 
-                let token = egg_mode::auth::bearer_token(&consumer_token).await?;
+    summary += target
+        .subdirectory("follows", None)?
+        .sync(&mut BlueskyRepoImporter::<BlueskyFollow>::new(repo.clone()))
+        .await?;
 
-                let timeline = egg_mode::tweet::user_timeline("maxdeviant", true, false, &token)
-                    .with_page_size(200);
+    summary += target
+        .subdirectory("blocks", None)?
+        .sync(&mut BlueskyRepoImporter::<BlueskyBlock>::new(repo))
+        .await?;
 
-                let (mut timeline, mut feed) = timeline.start().await?;
+    Ok(summary)
+}
 
-                'fetch_tweets: loop {
-                    for tweet in feed.response.clone() {
-                        let tweet = Tweet::from(tweet);
+async fn sync_lastfm(
+    account: &LastfmAccount,
+    user: Option<String>,
+    include_metadata: bool,
+    target: &SyncTarget,
+) -> Result<SyncSummary, PluckError> {
+    let lastfm_user = resolve_option("lastfm", "user", user, account.user.clone(), "LASTFM_USER")?;
+    let lastfm_api_key = account
+        .api_key
+        .clone()
+        .unwrap_or_else(|| Credential::env("LASTFM_API_KEY"))
+        .resolve()?;
+
+    let mut lastfm_fetcher = LastfmFetcher::new(lastfm_user, lastfm_api_key);
+    lastfm_fetcher.with_retry_policy(target.retry_policy.clone());
+
+    if include_metadata || account.include_metadata.unwrap_or(false) {
+        lastfm_fetcher.include_metadata();
+    }
 
-                        let year = tweet.created_at.year();
-                        let is_new_tweet = tweets_by_year
-                            .entry(year)
-                            .or_insert(IndexSet::new())
-                            .insert(tweet);
+    // Only full syncs page through the entire history, so only they are worth
+    // caching, including when one is resumed without `--full-sync`.
+    if is_full_sync::<Track>(&target.storage, &target.options).await? {
+        lastfm_fetcher.use_cache();
+    }
 
-                        if !is_new_tweet {
-                            break 'fetch_tweets;
-                        }
-                    }
+    target.sync(&mut lastfm_fetcher).await
+}
 
-                    (timeline, feed) = timeline.older(None).await?;
+async fn sync_twitter(
+    account: &TwitterAccount,
+    screen_name: Option<String>,
+    target: &SyncTarget,
+) -> Result<SyncSummary, PluckError> {
+    let twitter_screen_name = resolve_option(
+        "twitter",
+        "screen_name",
+        screen_name,
+        account.screen_name.clone(),
+        "TWITTER_SCREEN_NAME",
+    )?;
+    let twitter_consumer_key = account
+        .consumer_key
+        .clone()
+        .unwrap_or_else(|| Credential::env("TWITTER_CONSUMER_KEY"))
+        .resolve()?;
+    let twitter_consumer_secret = account
+        .consumer_secret
+        .clone()
+        .unwrap_or_else(|| Credential::env("TWITTER_CONSUMER_SECRET"))
+        .resolve()?;
+
+    let consumer_token = egg_mode::KeyPair::new(twitter_consumer_key, twitter_consumer_secret);
+
+    let token = egg_mode::auth::bearer_token(&consumer_token).await?;
+
+    let mut timeline_fetcher = TwitterTimelineFetcher::new(twitter_screen_name, &token);
+    timeline_fetcher.with_retry_policy(target.retry_policy.clone());
+
+    if let Some(media_store) = target.media_store() {
+        timeline_fetcher.with_media_store(media_store);
+    }
 
-                    tokio::time::sleep(Duration::from_millis(1000)).await;
-                }
+    target.sync(&mut timeline_fetcher).await
+}
 
-                for (year, mut tweets) in tweets_by_year {
-                    tweets.sort_unstable_by(|a, b| b.id.cmp(&a.id));
+/// The outcome of syncing one account as part of `pluck sync`.
+struct SyncReport {
+    label: String,
+    result: Result<SyncSummary, String>,
+    duration: Duration,
+}
 
-                    let mut file = File::create(output_dir.join(format!("{}.toml", year))).await?;
-                    file.write_all(toml::to_string_pretty(&TwitterYearData { tweets })?.as_bytes())
-                        .await?;
+/// Spawns a task that syncs every account of a source, one task per account,
+/// returning each task along with the account's label.
+fn spawn_source_syncs<A, F, Fut>(
+    source_name: &'static str,
+    config: &SourceConfig<A>,
+    args: &SyncArgs,
+    rate_limit_args: &RateLimitArgs,
+    sync_account: F,
+) -> Vec<(String, JoinHandle<SyncReport>)>
+where
+    A: Clone + Send + 'static,
+    F: Fn(A, SyncTarget) -> Fut,
+    Fut: Future<Output = Result<SyncSummary, PluckError>> + Send + 'static,
+{
+    config
+        .accounts()
+        .into_iter()
+        .map(|(account_name, account)| {
+            let label = match account_name {
+                Some(account_name) => format!("{}/{}", source_name, account_name),
+                None => source_name.to_string(),
+            };
+
+            let target = SyncTarget::new(
+                source_name,
+                config,
+                account_name,
+                None,
+                args,
+                rate_limit_args,
+            );
+            let sync = target.map(|target| sync_account(account.clone(), target));
+
+            let task_label = label.clone();
+
+            let handle = tokio::spawn(async move {
+                let label = task_label;
+                let started_at = Instant::now();
+
+                let result = match sync {
+                    Ok(sync) => sync.await,
+                    Err(err) => Err(err),
+                };
+
+                let result = result.map_err(|err| format!("{:#}", anyhow::Error::from(err)));
+
+                if let Err(err) = &result {
+                    eprintln!("[{}] Error: {}", label, err);
                 }
-            } else {
-                let archive_files = vec![
-                    "../../twitter-backup/data/tweets.js",
-                    "../../twitter-backup/data/tweets-part1.js",
-                ];
-
-                let archive_importer = TwitterArchiveImporter::new(archive_files);
-
-                let tweets = archive_importer.get_tweets()?;
-                for tweet in tweets {
-                    let tweet = Tweet::from(tweet);
-
-                    let year = tweet.created_at.year();
-                    let is_new_tweet = tweets_by_year
-                        .entry(year)
-                        .or_insert(IndexSet::new())
-                        .insert(tweet);
+
+                SyncReport {
+                    label,
+                    result,
+                    duration: started_at.elapsed(),
                 }
+            });
 
-                for (year, mut tweets) in tweets_by_year {
-                    tweets.sort_unstable_by(|a, b| b.id.cmp(&a.id));
+            (label, handle)
+        })
+        .collect()
+}
 
-                    let mut file = File::create(output_dir.join(format!("{}.toml", year))).await?;
-                    file.write_all(toml::to_string_pretty(&TwitterYearData { tweets })?.as_bytes())
-                        .await?;
-                }
+/// Prints a table summarizing how each account's sync went.
+fn print_summary(reports: &[SyncReport]) {
+    let label_width = reports
+        .iter()
+        .map(|report| report.label.len())
+        .chain(["SOURCE".len()])
+        .max()
+        .unwrap_or_default();
+
+    println!();
+    println!(
+        "{:<label_width$}  {:>9}  {:>8}  STATUS",
+        "SOURCE", "NEW ITEMS", "DURATION"
+    );
+
+    for report in reports {
+        let (new_items, status) = match &report.result {
+            Ok(summary) => (summary.items_written.to_string(), "ok".to_string()),
+            Err(err) => ("-".to_string(), format!("failed: {}", err)),
+        };
+
+        println!(
+            "{:<label_width$}  {:>9}  {:>7.1}s  {}",
+            report.label,
+            new_items,
+            report.duration.as_secs_f64(),
+            status
+        );
+    }
+}
+
+/// Returns the value of an option, preferring the command line over the config
+/// file and the config file over the environment.
+fn resolve_option(
+    source_name: &'static str,
+    option: &'static str,
+    arg: Option<String>,
+    config: Option<String>,
+    env_var: &str,
+) -> Result<String, PluckError> {
+    arg.or(config)
+        .or_else(|| env::var(env_var).ok())
+        .ok_or_else(|| {
+            ConfigError::Missing {
+                source_name,
+                option,
             }
-        }
+            .into()
+        })
+}
+
+/// Imports everything in a Twitter archive, with tweets stored in the target's
+/// output directory and everything else in subdirectories alongside them.
+///
+/// Followers, following and the profile aren't timestamped in a meaningful
+/// way, so they are always stored in a single partition.
+async fn import_twitter_archive(
+    archive: Arc<TwitterArchive>,
+    target: &SyncTarget,
+) -> Result<(), PluckError> {
+    let mut tweet_importer = TwitterArchiveImporter::new(archive.clone());
+
+    if let Some(media_store) = target.media_store() {
+        tweet_importer.with_media_store(media_store);
     }
 
+    target.sync(&mut tweet_importer).await?;
+
+    target
+        .subdirectory("likes", None)?
+        .sync(&mut TwitterArchiveDataImporter::<ArchivedLikeWrapper>::new(
+            archive.clone(),
+        ))
+        .await?;
+
+    target
+        .subdirectory("dms", None)?
+        .sync(&mut TwitterArchiveDataImporter::<
+            ArchivedDmConversationWrapper,
+        >::new(archive.clone()))
+        .await?;
+
+    target
+        .subdirectory("followers", Some(Partitioning::Single))?
+        .sync(&mut TwitterArchiveDataImporter::<ArchivedFollowerWrapper>::new(archive.clone()))
+        .await?;
+
+    target
+        .subdirectory("following", Some(Partitioning::Single))?
+        .sync(&mut TwitterArchiveDataImporter::<ArchivedFollowingWrapper>::new(archive.clone()))
+        .await?;
+
+    target
+        .subdirectory("profile", Some(Partitioning::Single))?
+        .sync(&mut TwitterProfileImporter::new(archive))
+        .await?;
+
     Ok(())
 }
 
-async fn get_latest_year_data(
-    target_dir: &Path,
-) -> Result<Option<(i32, YearData)>, Box<dyn std::error::Error>> {
-    let mut files = Vec::new();
+/// Converts an archive, along with the subdirectories that syncs archive other
+/// kinds of data into, from one storage format to another.
+///
+/// Downloaded media and repositories are copied over as they are.
+struct Conversion {
+    from: StorageFormat,
+    to: StorageFormat,
+    from_partitioning: Partitioning,
+    to_partitioning: Partitioning,
+    source_dir: PathBuf,
+    target_dir: PathBuf,
+}
+
+impl Conversion {
+    /// Converts the `T` records in the given subdirectory of the source archive
+    /// (or the archive itself), if it holds any.
+    ///
+    /// The partitioning is only given for subdirectories that syncs always
+    /// partition the same way, like `followers`.
+    async fn convert_if_present<T: Record>(
+        &self,
+        subdirectory: Option<&str>,
+        partitioning: Option<Partitioning>,
+    ) -> Result<bool, PluckError> {
+        let (source_dir, target_dir) = match subdirectory {
+            Some(name) => (self.source_dir.join(name), self.target_dir.join(name)),
+            None => (self.source_dir.clone(), self.target_dir.clone()),
+        };
+
+        if !source_dir.is_dir() {
+            return Ok(false);
+        }
 
-    for entry in std::fs::read_dir(target_dir)? {
-        let entry = entry?;
+        let source = self
+            .from
+            .open_read_only(&source_dir, partitioning.unwrap_or(self.from_partitioning))?;
 
-        let path = entry.path();
-        if path.is_file() && path.extension() == Some(OsStr::new("toml")) {
-            files.push(path);
+        if !source.contains::<T>().await? {
+            return Ok(false);
         }
-    }
 
-    files.sort_unstable();
+        let target = open_storage(
+            &target_dir,
+            self.to,
+            partitioning.unwrap_or(self.to_partitioning),
+            false,
+        )?;
 
-    if let Some(filepath) = files.pop() {
-        let mut file = File::open(&filepath).await?;
-        let mut buffer = String::new();
-        file.read_to_string(&mut buffer).await?;
+        let count = convert::<T>(&source, &target, target.location()).await?;
 
-        let year: i32 = filepath
-            .file_stem()
-            .expect("no filename")
-            .to_str()
-            .expect("invalid filename")
-            .parse()?;
-        let year_data: YearData = toml::from_str(&buffer)?;
+        println!("Converted {} {}", count, T::COLLECTION);
 
-        Ok(Some((year, year_data)))
-    } else {
-        Ok(None)
+        copy_archive_files(&source_dir, &target_dir).await?;
+
+        Ok(true)
     }
 }
 
-async fn get_latest_bluesky_year_data(
-    target_dir: &Path,
-) -> Result<Option<(i32, BlueskyYearData)>, Box<dyn std::error::Error>> {
-    let mut files = Vec::new();
+async fn run(args: Args) -> anyhow::Result<()> {
+    let config = Config::load_or_default(args.config.as_deref())?;
 
-    for entry in std::fs::read_dir(target_dir)? {
-        let entry = entry?;
+    match args.command {
+        Command::Sync { sync: sync_args } => {
+            let started_at = Instant::now();
+            let mut handles = Vec::new();
+
+            if let Some(config) = &config.bluesky {
+                handles.extend(spawn_source_syncs(
+                    "bluesky",
+                    config,
+                    &sync_args,
+                    &args.rate_limit,
+                    |account, target| async move {
+                        let collections = BlueskyCollections::for_account(&account, false, false);
+
+                        sync_bluesky(&account, BlueskyLoginArgs::default(), collections, &target)
+                            .await
+                    },
+                ));
+            }
 
-        let path = entry.path();
-        if path.is_file() && path.extension() == Some(OsStr::new("toml")) {
-            files.push(path);
-        }
-    }
+            if let Some(config) = &config.lastfm {
+                handles.extend(spawn_source_syncs(
+                    "lastfm",
+                    config,
+                    &sync_args,
+                    &args.rate_limit,
+                    |account, target| async move { sync_lastfm(&account, None, false, &target).await },
+                ));
+            }
 
-    files.sort_unstable();
+            if let Some(config) = &config.twitter {
+                handles.extend(spawn_source_syncs(
+                    "twitter",
+                    config,
+                    &sync_args,
+                    &args.rate_limit,
+                    |account, target| async move { sync_twitter(&account, None, &target).await },
+                ));
+            }
 
-    if let Some(filepath) = files.pop() {
-        let mut file = File::open(&filepath).await?;
-        let mut buffer = String::new();
-        file.read_to_string(&mut buffer).await?;
+            if handles.is_empty() {
+                anyhow::bail!("no sources are configured");
+            }
 
-        let year: i32 = filepath
-            .file_stem()
-            .expect("no filename")
-            .to_str()
-            .expect("invalid filename")
-            .parse()?;
-        let year_data: BlueskyYearData = toml::from_str(&buffer)?;
+            let mut reports = Vec::new();
+            for (label, handle) in handles {
+                // A sync that panicked is reported as failed like any other, rather
+                // than losing the reports of the syncs that are still running.
+                let report = handle.await.unwrap_or_else(|err| {
+                    eprintln!("[{}] Error: {}", label, err);
+
+                    SyncReport {
+                        label,
+                        result: Err(err.to_string()),
+                        duration: started_at.elapsed(),
+                    }
+                });
 
-        Ok(Some((year, year_data)))
-    } else {
-        Ok(None)
-    }
-}
+                reports.push(report);
+            }
 
-async fn get_latest_twitter_year_data(
-    target_dir: &Path,
-) -> Result<Option<(i32, TwitterYearData)>, Box<dyn std::error::Error>> {
-    let mut files = Vec::new();
+            print_summary(&reports);
 
-    for entry in std::fs::read_dir(target_dir)? {
-        let entry = entry?;
+            let failures = reports
+                .iter()
+                .filter(|report| report.result.is_err())
+                .count();
 
-        let path = entry.path();
-        if path.is_file() && path.extension() == Some(OsStr::new("toml")) {
-            files.push(path);
+            if failures > 0 {
+                anyhow::bail!("{} of {} syncs failed", failures, reports.len());
+            }
         }
-    }
+        Command::Bluesky {
+            command,
+            source,
+            login,
+            include_reposts,
+            with_threads,
+        } => {
+            let config = config.bluesky.unwrap_or_default();
 
-    files.sort_unstable();
+            let (source, login, likes_only, repo) = match command {
+                Some(BlueskyCommand::Likes { source, login }) => (source, login, true, None),
+                Some(BlueskyCommand::Repo { source, login, car }) => {
+                    (source, login, false, Some(car))
+                }
+                None => (source, login, false, None),
+            };
 
-    if let Some(filepath) = files.pop() {
-        let mut file = File::open(&filepath).await?;
-        let mut buffer = String::new();
-        file.read_to_string(&mut buffer).await?;
+            let (account_name, account) = config.account("bluesky", source.account.as_deref())?;
 
-        let year: i32 = filepath
-            .file_stem()
-            .expect("no filename")
-            .to_str()
-            .expect("invalid filename")
-            .parse()?;
-        let year_data: TwitterYearData = toml::from_str(&buffer)?;
+            let target = SyncTarget::new(
+                "bluesky",
+                &config,
+                account_name,
+                source.output_dir,
+                &source.sync,
+                &args.rate_limit,
+            )?;
 
-        Ok(Some((year, year_data)))
-    } else {
-        Ok(None)
+            match repo {
+                Some(car) => {
+                    import_bluesky_repo(account, login, car, &target).await?;
+                }
+                None => {
+                    let collections = if likes_only {
+                        BlueskyCollections {
+                            posts: false,
+                            reposts: false,
+                            likes: true,
+                            threads: false,
+                        }
+                    } else {
+                        BlueskyCollections::for_account(account, include_reposts, with_threads)
+                    };
+
+                    sync_bluesky(account, login, collections, &target).await?;
+                }
+            }
+        }
+        Command::Lastfm {
+            source,
+            user,
+            include_metadata,
+        } => {
+            let config = config.lastfm.unwrap_or_default();
+            let (account_name, account) = config.account("lastfm", source.account.as_deref())?;
+
+            let target = SyncTarget::new(
+                "lastfm",
+                &config,
+                account_name,
+                source.output_dir,
+                &source.sync,
+                &args.rate_limit,
+            )?;
+
+            sync_lastfm(account, user, include_metadata, &target).await?;
+        }
+        Command::Twitter {
+            source,
+            screen_name,
+            archive,
+        } => {
+            let config = config.twitter.unwrap_or_default();
+            let (account_name, account) = config.account("twitter", source.account.as_deref())?;
+
+            let target = SyncTarget::new(
+                "twitter",
+                &config,
+                account_name,
+                source.output_dir,
+                &source.sync,
+                &args.rate_limit,
+            )?;
+
+            match archive {
+                Some(archive) => {
+                    import_twitter_archive(Arc::new(TwitterArchive::open(archive)?), &target)
+                        .await?;
+                }
+                None => {
+                    sync_twitter(account, screen_name, &target).await?;
+                }
+            }
+        }
+        Command::Convert {
+            from,
+            to,
+            from_partition_by,
+            to_partition_by,
+            source_dir,
+            target_dir,
+        } => {
+            let conversion = Conversion {
+                from: from.into(),
+                to: to.into(),
+                from_partitioning: from_partition_by.into(),
+                to_partitioning: to_partition_by.into(),
+                source_dir: source_dir.clone(),
+                target_dir,
+            };
+
+            let single = Some(Partitioning::Single);
+
+            let converted_any = conversion.convert_if_present::<Track>(None, None).await?
+                | conversion
+                    .convert_if_present::<BlueskyPost>(None, None)
+                    .await?
+                | conversion.convert_if_present::<Tweet>(None, None).await?
+                | conversion
+                    .convert_if_present::<BlueskyRepost>(Some("reposts"), None)
+                    .await?
+                | conversion
+                    .convert_if_present::<BlueskyLike>(Some("likes"), None)
+                    .await?
+                | conversion
+                    .convert_if_present::<Like>(Some("likes"), None)
+                    .await?
+                | conversion
+                    .convert_if_present::<BlueskyThread>(Some("threads"), None)
+                    .await?
+                | conversion
+                    .convert_if_present::<BlueskyFollow>(Some("follows"), None)
+                    .await?
+                | conversion
+                    .convert_if_present::<BlueskyBlock>(Some("blocks"), None)
+                    .await?
+                | conversion
+                    .convert_if_present::<DirectMessage>(Some("dms"), None)
+                    .await?
+                | conversion
+                    .convert_if_present::<Follower>(Some("followers"), single)
+                    .await?
+                | conversion
+                    .convert_if_present::<Following>(Some("following"), single)
+                    .await?
+                | conversion
+                    .convert_if_present::<TwitterProfile>(Some("profile"), single)
+                    .await?;
+
+            if !converted_any {
+                anyhow::bail!("no records found in {}", source_dir.display());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use pluck::config::ConfigError;
+    use pluck::StorageError;
+
+    use super::*;
+
+    #[test]
+    fn errors_are_mapped_to_their_exit_codes() {
+        let exit_code_of = |err: PluckError| exit_code(&err.into());
+
+        assert_eq!(
+            exit_code_of(PluckError::LastfmApi {
+                code: 10,
+                message: "Invalid API key".to_string(),
+            }),
+            ExitCode::from(3)
+        );
+        assert_eq!(
+            exit_code_of(PluckError::BlueskyApi {
+                status: 429,
+                message: "RateLimitExceeded".to_string(),
+                retry_after: None,
+            }),
+            ExitCode::from(4)
+        );
+        assert_eq!(
+            exit_code_of(PluckError::BlueskyApi {
+                status: 500,
+                message: "InternalServerError".to_string(),
+                retry_after: None,
+            }),
+            ExitCode::from(5)
+        );
+        assert_eq!(
+            exit_code_of(PluckError::InvalidBlueskyRepo("truncated".to_string())),
+            ExitCode::from(6)
+        );
+        assert_eq!(
+            exit_code_of(PluckError::Storage {
+                path: PathBuf::from("2024.toml"),
+                source: StorageError::InvalidFileName,
+            }),
+            ExitCode::from(7)
+        );
+        assert_eq!(
+            exit_code_of(PluckError::Config(ConfigError::MissingEnvVar(
+                "LASTFM_API_KEY".to_string()
+            ))),
+            ExitCode::from(8)
+        );
+        assert_eq!(
+            exit_code(&anyhow::anyhow!("1 of 2 syncs failed")),
+            ExitCode::FAILURE
+        );
     }
 }
@@ -0,0 +1,330 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+use crate::retry::{parse_retry_after, RetryPolicy};
+use crate::storage::write_atomic;
+use crate::PluckError;
+
+/// The name of the directory, within an archive, that media is stored in.
+pub const MEDIA_DIR_NAME: &str = "media";
+
+/// The name of the file, within the media directory, that lists the URL each
+/// file was downloaded from.
+const DOWNLOADS_FILE_NAME: &str = "downloads.jsonl";
+
+/// A file that has been saved to a [`MediaStore`].
+///
+/// Media in archived records keeps track of its downloaded copy, which is left
+/// out of the record's comparisons, so that downloading media for an item that
+/// has already been archived doesn't archive it a second time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredMedia {
+    /// The path to the file, relative to the archive, e.g. `media/ab/ab12….jpg`.
+    pub path: String,
+
+    /// The hex-encoded SHA-256 hash of the file's contents.
+    pub sha256: String,
+}
+
+/// A content-addressed store for the photos, videos and GIFs referenced by an
+/// archive.
+///
+/// Each file is named after the hash of its contents and sharded by the first
+/// two characters of that hash, so the same file is only ever stored once.
+/// Downloads are recorded by URL, so that syncing an item again doesn't
+/// download its media again.
+#[derive(Debug, Clone)]
+pub struct MediaStore {
+    output_dir: PathBuf,
+    client: reqwest::Client,
+    retry_policy: RetryPolicy,
+
+    /// The files downloaded so far, by the URL they were downloaded from, which
+    /// are read from the downloads file when they're first needed.
+    downloads: Arc<Mutex<Option<HashMap<String, StoredMedia>>>>,
+}
+
+/// A line of the downloads file.
+#[derive(Debug, Serialize, Deserialize)]
+struct Download {
+    url: String,
+    path: String,
+    sha256: String,
+}
+
+impl MediaStore {
+    /// Creates a store that keeps media in the `media` directory of the given
+    /// archive's output directory.
+    pub fn new(output_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            output_dir: output_dir.into(),
+            client: reqwest::Client::default(),
+            retry_policy: RetryPolicy::default(),
+            downloads: Arc::default(),
+        }
+    }
+
+    pub fn with_retry_policy(&mut self, retry_policy: RetryPolicy) -> &mut Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Saves the given contents, unless a file with the same contents has
+    /// already been saved.
+    pub async fn save(
+        &self,
+        contents: &[u8],
+        extension: Option<&str>,
+    ) -> Result<StoredMedia, PluckError> {
+        let sha256 = format!("{:x}", Sha256::digest(contents));
+
+        let file_name = match extension {
+            Some(extension) => format!("{}.{}", sha256, extension),
+            None => sha256.clone(),
+        };
+        let path = format!("{}/{}/{}", MEDIA_DIR_NAME, &sha256[..2], file_name);
+
+        let filepath = self.output_dir.join(&path);
+
+        if !filepath.exists() {
+            if let Some(parent) = filepath.parent() {
+                tokio::fs::create_dir_all(parent)
+                    .await
+                    .map_err(|err| PluckError::storage(parent, err))?;
+            }
+
+            write_atomic(&filepath, contents, false).await?;
+        }
+
+        Ok(StoredMedia { path, sha256 })
+    }
+
+    /// Downloads the file at the given URL and saves it, unless it has already
+    /// been downloaded.
+    ///
+    /// The file extension is taken from the URL, unless one is given.
+    pub async fn download(
+        &self,
+        url: &str,
+        extension: Option<&str>,
+    ) -> Result<StoredMedia, PluckError> {
+        let mut downloads = self.downloads.lock().await;
+
+        let downloads = match &mut *downloads {
+            Some(downloads) => downloads,
+            None => downloads.insert(self.read_downloads().await?),
+        };
+
+        if let Some(stored_media) = downloads.get(url) {
+            if self.output_dir.join(&stored_media.path).exists() {
+                return Ok(stored_media.clone());
+            }
+        }
+
+        let contents = self.retry_policy.retry(|| self.request(url)).await?;
+
+        let extension = extension.or_else(|| extension_of_url(url));
+
+        let stored_media = self.save(&contents, extension).await?;
+
+        self.record_download(url, &stored_media).await?;
+        downloads.insert(url.to_string(), stored_media.clone());
+
+        Ok(stored_media)
+    }
+
+    fn downloads_filepath(&self) -> PathBuf {
+        self.output_dir
+            .join(MEDIA_DIR_NAME)
+            .join(DOWNLOADS_FILE_NAME)
+    }
+
+    /// Reads the downloads file, skipping any line that was cut short by an
+    /// interrupted sync.
+    async fn read_downloads(&self) -> Result<HashMap<String, StoredMedia>, PluckError> {
+        let filepath = self.downloads_filepath();
+
+        let contents = match tokio::fs::read_to_string(&filepath).await {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
+            Err(err) => return Err(PluckError::storage(&filepath, err)),
+        };
+
+        Ok(contents
+            .lines()
+            .filter_map(|line| serde_json::from_str::<Download>(line).ok())
+            .map(|download| {
+                let stored_media = StoredMedia {
+                    path: download.path,
+                    sha256: download.sha256,
+                };
+
+                (download.url, stored_media)
+            })
+            .collect())
+    }
+
+    /// Appends a download to the downloads file.
+    async fn record_download(
+        &self,
+        url: &str,
+        stored_media: &StoredMedia,
+    ) -> Result<(), PluckError> {
+        let filepath = self.downloads_filepath();
+
+        let mut line = serde_json::to_string(&Download {
+            url: url.to_string(),
+            path: stored_media.path.clone(),
+            sha256: stored_media.sha256.clone(),
+        })
+        .map_err(|err| PluckError::storage(&filepath, err))?;
+        line.push('\n');
+
+        let append = async {
+            let mut file = tokio::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&filepath)
+                .await?;
+
+            file.write_all(line.as_bytes()).await?;
+            file.sync_data().await
+        };
+
+        append
+            .await
+            .map_err(|err| PluckError::storage(&filepath, err))
+    }
+
+    async fn request(&self, url: &str) -> Result<Vec<u8>, PluckError> {
+        let response = self.client.get(url).send().await?;
+
+        let status = response.status();
+
+        if !status.is_success() {
+            let retry_after = response
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(parse_retry_after);
+
+            return Err(PluckError::HttpStatus {
+                url: url.to_string(),
+                status,
+                retry_after,
+            });
+        }
+
+        Ok(response.bytes().await?.to_vec())
+    }
+}
+
+/// Returns the file extension of the last segment of the given URL's path
+/// (or of the given file name).
+pub fn extension_of_url(url: &str) -> Option<&str> {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    let file_name = path.rsplit('/').next().unwrap_or(path);
+    let (_, extension) = file_name.rsplit_once('.')?;
+
+    if extension.is_empty() || extension.len() > 5 {
+        return None;
+    }
+
+    Some(extension)
+}
+
+/// Returns the usual file extension for the given MIME type.
+pub fn extension_of_mime_type(mime_type: &str) -> Option<&'static str> {
+    match mime_type {
+        "image/jpeg" => Some("jpg"),
+        "image/png" => Some("png"),
+        "image/gif" => Some("gif"),
+        "image/webp" => Some("webp"),
+        "image/heic" => Some("heic"),
+        "video/mp4" => Some("mp4"),
+        "video/quicktime" => Some("mov"),
+        "video/webm" => Some("webm"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns an empty directory for a test to write to.
+    fn empty_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("pluck-{}-{}", name, std::process::id()));
+
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    #[tokio::test]
+    async fn files_are_stored_once_under_their_hash() {
+        let dir = empty_dir("media-save");
+        let media_store = MediaStore::new(&dir);
+
+        let first = media_store.save(b"photo", Some("jpg")).await.unwrap();
+        let second = media_store.save(b"photo", Some("jpg")).await.unwrap();
+
+        assert_eq!(first, second);
+        assert_eq!(
+            first.path,
+            format!("media/{}/{}.jpg", &first.sha256[..2], first.sha256)
+        );
+        assert_eq!(std::fs::read(dir.join(&first.path)).unwrap(), b"photo");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn recorded_downloads_are_not_downloaded_again() {
+        let dir = empty_dir("media-downloads");
+        // Nothing listens on this port, so any request for it fails.
+        let url = "http://127.0.0.1:9/photo.jpg";
+
+        let media_store = MediaStore::new(&dir);
+        let stored_media = media_store.save(b"photo", Some("jpg")).await.unwrap();
+        media_store
+            .record_download(url, &stored_media)
+            .await
+            .unwrap();
+
+        let mut media_store = MediaStore::new(&dir);
+        media_store.with_retry_policy(RetryPolicy {
+            max_attempts: 1,
+            ..RetryPolicy::default()
+        });
+
+        assert_eq!(media_store.download(url, None).await.unwrap(), stored_media);
+
+        // Files that have gone missing since are downloaded again.
+        std::fs::remove_file(dir.join(&stored_media.path)).unwrap();
+        assert!(media_store.download(url, None).await.is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn extensions_are_taken_from_the_last_path_segment() {
+        assert_eq!(
+            extension_of_url("https://pbs.twimg.com/media/abc.jpg?name=orig"),
+            Some("jpg")
+        );
+        assert_eq!(extension_of_url("1234-abc.mp4"), Some("mp4"));
+        assert_eq!(extension_of_url("https://example.com/media.d/file"), None);
+        assert_eq!(
+            extension_of_url("https://example.com/file.tar.gzipped"),
+            None
+        );
+    }
+}
@@ -0,0 +1,99 @@
+//! The records that are stored in archives.
+//!
+//! Records are serialized to TOML among other formats, and TOML can't have
+//! plain values after a table, so fields that serialize as tables (structs, and
+//! lists of them) must come after all of the others.
+
+/// Implements `PartialEq`, `Eq` and `Hash` for a record in terms of the fields
+/// that its [`Record::id`] is made of.
+///
+/// Every storage backend has to agree on which records are the same: the file
+/// backends dedupe records by `Hash`/`Eq`, while SQLite keys them by ID. The
+/// rest of a record's details are merged in by [`Record::refresh`] instead.
+macro_rules! record_identity {
+    ($record:ty, $($field:ident),+) => {
+        impl PartialEq for $record {
+            fn eq(&self, other: &Self) -> bool {
+                $(self.$field == other.$field)&&+
+            }
+        }
+
+        impl Eq for $record {}
+
+        impl std::hash::Hash for $record {
+            fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+                $(std::hash::Hash::hash(&self.$field, state);)+
+            }
+        }
+    };
+}
+
+mod bluesky;
+mod lastfm;
+mod twitter;
+
+use std::hash::Hash;
+
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+pub use bluesky::*;
+pub use lastfm::*;
+pub use twitter::*;
+
+/// An item that can be stored in an archive.
+pub trait Record: Hash + Eq + Serialize + DeserializeOwned + Send + Sync + 'static {
+    /// The name of the collection these records are stored under, e.g. `posts`.
+    const COLLECTION: &'static str;
+
+    /// The key that records are sorted by (in descending order) within the archive.
+    type SortKey: Ord;
+
+    /// Returns the unique ID of this record.
+    fn id(&self) -> String;
+
+    /// Returns the time this record was created (or, for scrobbles, listened to).
+    fn timestamp(&self) -> DateTime<Utc>;
+
+    /// Returns the key to sort this record by.
+    fn sort_key(&self) -> Self::SortKey;
+
+    /// Returns when the source indexed this record, for records whose source
+    /// orders them by when they were indexed rather than by [`Self::timestamp`].
+    fn indexed_at(&self) -> Option<DateTime<Utc>> {
+        None
+    }
+
+    /// Updates this (archived) record with the details of a freshly fetched copy
+    /// of it, for records with details that change over time, like engagement
+    /// counts.
+    ///
+    /// Only details that aren't part of the record's `Hash`/`Eq` identity may be
+    /// updated. Returns whether anything changed. Archived records are kept as
+    /// they are by default.
+    fn refresh(&mut self, _fetched: Self) -> bool {
+        false
+    }
+
+    /// Marks this (archived) record as deleted upstream, for records that keep
+    /// track of deletions.
+    ///
+    /// Returns whether anything changed. Records that are already marked as
+    /// deleted keep the time they were first found to be missing.
+    fn mark_deleted(&mut self, _deleted_at: DateTime<Utc>) -> bool {
+        false
+    }
+}
+
+/// Fills in a detail that's missing from an archived record with the fetched
+/// copy's, returning whether it changed.
+fn fill<T>(archived: &mut Option<T>, fetched: &mut Option<T>) -> bool {
+    if archived.is_some() || fetched.is_none() {
+        return false;
+    }
+
+    *archived = fetched.take();
+
+    true
+}
@@ -0,0 +1,708 @@
+use std::hash::{Hash, Hasher};
+
+use chrono::{DateTime, Utc};
+use indexmap::IndexSet;
+use serde::{Deserialize, Serialize};
+
+use crate::media::StoredMedia;
+use crate::models::{fill, MediaType, Record};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BlueskyPost {
+    pub uri: String,
+    pub created_at: DateTime<Utc>,
+    pub text: String,
+
+    /// The languages the text is written in, e.g. `en`.
+    pub langs: Option<Vec<String>>,
+
+    /// The labels (or content warnings) the author applied to the post, e.g. `nudity`.
+    pub labels: Option<Vec<String>>,
+
+    /// The CID of the current version of the post.
+    pub cid: Option<String>,
+
+    /// When the post was indexed by the AppView it was fetched from.
+    pub indexed_at: Option<DateTime<Utc>>,
+
+    pub like_count: Option<i64>,
+    pub repost_count: Option<i64>,
+    pub reply_count: Option<i64>,
+    pub quote_count: Option<i64>,
+
+    /// When a sync first found the post to be missing upstream, presumably
+    /// because it was deleted.
+    pub deleted_at: Option<DateTime<Utc>>,
+
+    pub in_reply_to: Option<BlueskyPostReply>,
+
+    /// The links, mentions and hashtags within the text.
+    pub facets: Option<Vec<BlueskyFacet>>,
+
+    pub embed: Option<BlueskyEmbed>,
+
+    /// The earlier versions of the post, oldest first, for posts whose record
+    /// has been rewritten since they were archived.
+    pub revisions: Option<Vec<BlueskyPostRevision>>,
+}
+
+impl BlueskyPost {
+    fn engagement(&self) -> (Option<i64>, Option<i64>, Option<i64>, Option<i64>) {
+        (
+            self.like_count,
+            self.repost_count,
+            self.reply_count,
+            self.quote_count,
+        )
+    }
+
+    /// Returns whether the post's record has been rewritten since it was
+    /// archived, going by the CIDs of the two copies.
+    ///
+    /// Posts archived before CIDs were recorded can't be told apart from
+    /// rewritten ones, so they never count as rewritten.
+    fn is_rewritten_as(&self, fetched: &Self) -> bool {
+        match (&self.cid, &fetched.cid) {
+            (Some(cid), Some(fetched_cid)) => cid != fetched_cid,
+            _ => false,
+        }
+    }
+
+    /// Fills in the content that's missing from the post, such as the facets
+    /// of posts archived before they were recorded, returning whether anything
+    /// changed.
+    fn fill_in(&mut self, fetched: &mut Self) -> bool {
+        let mut changed = false;
+
+        changed |= fill(&mut self.langs, &mut fetched.langs);
+        changed |= fill(&mut self.labels, &mut fetched.labels);
+        changed |= fill(&mut self.facets, &mut fetched.facets);
+        changed |= fill(&mut self.embed, &mut fetched.embed);
+
+        // Media that has been downloaded since the post was archived is
+        // recorded, as the archived copy of the embed wins otherwise.
+        if let (Some(embed), Some(fetched_embed)) = (&mut self.embed, &mut fetched.embed) {
+            for (media, fetched_media) in
+                embed.media_mut().into_iter().zip(fetched_embed.media_mut())
+            {
+                changed |= media.fill_stored_media(fetched_media);
+            }
+        }
+
+        changed
+    }
+
+    /// Replaces the content of the post with that of the given copy of it,
+    /// keeping the current content as a revision.
+    fn revise(&mut self, fetched: &mut Self) {
+        let revision = BlueskyPostRevision {
+            replaced_at: Utc::now(),
+            cid: self.cid.take(),
+            created_at: self.created_at,
+            text: std::mem::replace(&mut self.text, std::mem::take(&mut fetched.text)),
+            langs: std::mem::replace(&mut self.langs, fetched.langs.take()),
+            labels: std::mem::replace(&mut self.labels, fetched.labels.take()),
+            facets: std::mem::replace(&mut self.facets, fetched.facets.take()),
+            embed: std::mem::replace(&mut self.embed, fetched.embed.take()),
+        };
+
+        self.created_at = fetched.created_at;
+        self.cid = fetched.cid.clone();
+        self.revisions.get_or_insert_with(Vec::new).push(revision);
+    }
+}
+
+/// An earlier version of a [`BlueskyPost`].
+#[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct BlueskyPostRevision {
+    /// When a sync found that this version had been replaced.
+    pub replaced_at: DateTime<Utc>,
+
+    /// The CID of this version of the post, if it was known.
+    pub cid: Option<String>,
+
+    pub created_at: DateTime<Utc>,
+    pub text: String,
+    pub langs: Option<Vec<String>>,
+    pub labels: Option<Vec<String>>,
+    pub facets: Option<Vec<BlueskyFacet>>,
+    pub embed: Option<BlueskyEmbed>,
+}
+
+/// The post that a post replies to.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BlueskyPostReply {
+    pub uri: String,
+    pub author_did: String,
+
+    /// The handle of the parent post's author, which isn't known for posts
+    /// read from a repository export.
+    pub author_handle: Option<String>,
+
+    /// The text of the parent post, as of when the reply was archived.
+    pub text: Option<String>,
+
+    /// Whether the parent post could be seen when the reply was last synced.
+    pub state: Option<BlueskyParentState>,
+
+    /// The post at the root of the thread.
+    pub root: Option<BlueskyRecordRef>,
+}
+
+impl BlueskyPostReply {
+    /// Updates the parent's state from a freshly fetched copy of the reply,
+    /// returning whether it changed.
+    ///
+    /// The parent's text is only filled in if it's missing, so that a snapshot
+    /// survives the parent being deleted.
+    fn refresh(&mut self, fetched: Self) -> bool {
+        let mut changed = false;
+
+        if fetched.state.is_some() && self.state != fetched.state {
+            self.state = fetched.state;
+            changed = true;
+        }
+
+        if self.text.is_none() && fetched.text.is_some() {
+            self.text = fetched.text;
+            changed = true;
+        }
+
+        if self.author_handle.is_none() && fetched.author_handle.is_some() {
+            self.author_handle = fetched.author_handle;
+            changed = true;
+        }
+
+        changed
+    }
+}
+
+// The parent's text and state are snapshots that change over time (and are
+// missing from older archives), so they are left out of comparisons. So is
+// the author's handle, which isn't known for posts read from a repository.
+impl PartialEq for BlueskyPostReply {
+    fn eq(&self, other: &Self) -> bool {
+        self.uri == other.uri && self.author_did == other.author_did
+    }
+}
+
+impl Eq for BlueskyPostReply {}
+
+impl Hash for BlueskyPostReply {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.uri.hash(state);
+        self.author_did.hash(state);
+    }
+}
+
+/// Whether the parent of a reply could be seen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlueskyParentState {
+    Visible,
+
+    /// The parent post has been deleted.
+    NotFound,
+
+    /// The parent post's author has blocked (or been blocked by) the account.
+    Blocked,
+}
+
+/// A range of a post's text that is annotated with a link, mention or hashtag.
+///
+/// The range is given in bytes of the UTF-8 encoded text.
+#[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct BlueskyFacet {
+    pub byte_start: usize,
+    pub byte_end: usize,
+    pub features: Vec<BlueskyFacetFeature>,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BlueskyFacetFeature {
+    Link { uri: String },
+    Mention { did: String },
+    Tag { tag: String },
+}
+
+/// Content embedded in a post, such as images or a quoted post.
+#[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BlueskyEmbed {
+    Images {
+        images: Vec<BlueskyMedia>,
+    },
+    Video {
+        video: BlueskyMedia,
+    },
+
+    /// A link card.
+    External {
+        uri: String,
+        title: String,
+        description: String,
+        thumb: Option<BlueskyMedia>,
+    },
+
+    /// A quoted post.
+    Record {
+        record: BlueskyRecordRef,
+    },
+
+    /// A quoted post accompanied by images, a video or a link card.
+    RecordWithMedia {
+        record: BlueskyRecordRef,
+        media: Box<BlueskyEmbed>,
+    },
+}
+
+impl BlueskyEmbed {
+    /// Returns the images and videos in the embed, including the thumbnail of a link card.
+    pub fn media_mut(&mut self) -> Vec<&mut BlueskyMedia> {
+        match self {
+            Self::Images { images } => images.iter_mut().collect(),
+            Self::Video { video } => vec![video],
+            Self::External { thumb, .. } => thumb.iter_mut().collect(),
+            Self::Record { .. } => Vec::new(),
+            Self::RecordWithMedia { media, .. } => media.media_mut(),
+        }
+    }
+}
+
+/// A reference to a specific version of a record, such as a quoted post.
+#[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct BlueskyRecordRef {
+    pub uri: String,
+    pub cid: String,
+}
+
+/// An image or video attached to a post.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BlueskyMedia {
+    pub r#type: MediaType,
+
+    /// The CID of the blob holding the media.
+    pub cid: String,
+
+    pub mime_type: String,
+    pub alt: Option<String>,
+
+    /// The path to the downloaded copy of the media, relative to the archive.
+    pub local_path: Option<String>,
+
+    /// The SHA-256 hash of the downloaded copy of the media.
+    pub sha256: Option<String>,
+}
+
+impl BlueskyMedia {
+    pub fn set_stored_media(&mut self, stored_media: StoredMedia) {
+        self.local_path = Some(stored_media.path);
+        self.sha256 = Some(stored_media.sha256);
+    }
+
+    /// Takes the downloaded copy of the same media from a freshly fetched copy
+    /// of it, if this one doesn't have one yet. Returns whether it did.
+    fn fill_stored_media(&mut self, fetched: &mut Self) -> bool {
+        if self.local_path.is_some() || fetched.local_path.is_none() || self != fetched {
+            return false;
+        }
+
+        self.local_path = fetched.local_path.take();
+        self.sha256 = fetched.sha256.take();
+
+        true
+    }
+}
+
+impl PartialEq for BlueskyMedia {
+    fn eq(&self, other: &Self) -> bool {
+        self.r#type == other.r#type
+            && self.cid == other.cid
+            && self.mime_type == other.mime_type
+            && self.alt == other.alt
+    }
+}
+
+impl Eq for BlueskyMedia {}
+
+impl Hash for BlueskyMedia {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.r#type.hash(state);
+        self.cid.hash(state);
+        self.mime_type.hash(state);
+        self.alt.hash(state);
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BlueskyYearData {
+    pub posts: IndexSet<BlueskyPost>,
+}
+
+// A post's content can change when its record is rewritten, in which case the
+// archived post is revised rather than archived a second time.
+record_identity!(BlueskyPost, uri);
+
+impl Record for BlueskyPost {
+    const COLLECTION: &'static str = "posts";
+
+    type SortKey = String;
+
+    fn id(&self) -> String {
+        self.uri.clone()
+    }
+
+    fn timestamp(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+
+    fn sort_key(&self) -> String {
+        self.uri.clone()
+    }
+
+    fn indexed_at(&self) -> Option<DateTime<Utc>> {
+        self.indexed_at
+    }
+
+    fn refresh(&mut self, mut fetched: Self) -> bool {
+        let mut changed = match (&mut self.in_reply_to, fetched.in_reply_to.take()) {
+            (Some(in_reply_to), Some(fetched)) => in_reply_to.refresh(fetched),
+            _ => false,
+        };
+
+        // The post can't have been deleted if it was just fetched.
+        if self.deleted_at.take().is_some() {
+            changed = true;
+        }
+
+        if self.is_rewritten_as(&fetched) {
+            self.revise(&mut fetched);
+            changed = true;
+        } else {
+            changed |= self.fill_in(&mut fetched);
+        }
+
+        if fetched.indexed_at.is_none() {
+            return changed;
+        }
+
+        changed |= self.cid != fetched.cid
+            || self.indexed_at != fetched.indexed_at
+            || self.engagement() != fetched.engagement();
+
+        self.cid = fetched.cid;
+        self.indexed_at = fetched.indexed_at;
+        self.like_count = fetched.like_count;
+        self.repost_count = fetched.repost_count;
+        self.reply_count = fetched.reply_count;
+        self.quote_count = fetched.quote_count;
+
+        changed
+    }
+
+    fn mark_deleted(&mut self, deleted_at: DateTime<Utc>) -> bool {
+        if self.deleted_at.is_some() {
+            return false;
+        }
+
+        self.deleted_at = Some(deleted_at);
+
+        true
+    }
+}
+
+impl From<IndexSet<BlueskyPost>> for BlueskyYearData {
+    fn from(posts: IndexSet<BlueskyPost>) -> Self {
+        Self { posts }
+    }
+}
+
+impl From<BlueskyYearData> for IndexSet<BlueskyPost> {
+    fn from(year_data: BlueskyYearData) -> Self {
+        year_data.posts
+    }
+}
+
+/// A post reposted by the owner of the archive.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BlueskyRepost {
+    pub reposted_at: DateTime<Utc>,
+    pub post_uri: String,
+    pub post_cid: String,
+    pub author_did: String,
+
+    /// The details of the reposted post, which aren't known for reposts read
+    /// from a repository export.
+    pub author_handle: Option<String>,
+    pub text: Option<String>,
+
+    /// When the reposted post was created.
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+// Reposts are identified by the reposted post, as the author feed doesn't give
+// the URI of the repost record. So when a post is unreposted and then reposted
+// again, only its first repost is kept (unless the two fall in different
+// partitions). The post's details aren't known for reposts read from a
+// repository export, so they're filled in by `refresh`.
+record_identity!(BlueskyRepost, post_uri);
+
+impl Record for BlueskyRepost {
+    const COLLECTION: &'static str = "reposts";
+
+    type SortKey = (DateTime<Utc>, String);
+
+    fn id(&self) -> String {
+        self.post_uri.clone()
+    }
+
+    fn timestamp(&self) -> DateTime<Utc> {
+        self.reposted_at
+    }
+
+    fn sort_key(&self) -> (DateTime<Utc>, String) {
+        (self.reposted_at, self.post_uri.clone())
+    }
+
+    fn refresh(&mut self, mut fetched: Self) -> bool {
+        let mut changed = false;
+
+        changed |= fill(&mut self.author_handle, &mut fetched.author_handle);
+        changed |= fill(&mut self.text, &mut fetched.text);
+        changed |= fill(&mut self.created_at, &mut fetched.created_at);
+
+        changed
+    }
+}
+
+/// A post liked by the owner of the archive.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BlueskyLike {
+    /// The URI of the like record itself, if it was visible to us.
+    pub uri: Option<String>,
+
+    /// When the post was liked, taken from the like record, or when the post
+    /// was indexed if the like record wasn't visible to us.
+    pub liked_at: DateTime<Utc>,
+
+    pub post_uri: String,
+    pub post_cid: String,
+    pub author_did: String,
+
+    /// The details of the liked post, which aren't known for likes read from a
+    /// repository export.
+    pub author_handle: Option<String>,
+    pub text: Option<String>,
+
+    /// When the liked post was created.
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+record_identity!(BlueskyLike, post_uri);
+
+impl Record for BlueskyLike {
+    const COLLECTION: &'static str = "likes";
+
+    type SortKey = (DateTime<Utc>, String);
+
+    fn id(&self) -> String {
+        self.post_uri.clone()
+    }
+
+    fn timestamp(&self) -> DateTime<Utc> {
+        self.liked_at
+    }
+
+    fn sort_key(&self) -> (DateTime<Utc>, String) {
+        (self.liked_at, self.post_uri.clone())
+    }
+
+    fn refresh(&mut self, mut fetched: Self) -> bool {
+        let mut changed = false;
+
+        changed |= fill(&mut self.uri, &mut fetched.uri);
+        changed |= fill(&mut self.author_handle, &mut fetched.author_handle);
+        changed |= fill(&mut self.text, &mut fetched.text);
+        changed |= fill(&mut self.created_at, &mut fetched.created_at);
+
+        changed
+    }
+}
+
+/// An account followed by the owner of the archive.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BlueskyFollow {
+    /// The URI of the follow record.
+    pub uri: String,
+
+    pub subject_did: String,
+    pub created_at: DateTime<Utc>,
+}
+
+record_identity!(BlueskyFollow, uri);
+
+impl Record for BlueskyFollow {
+    const COLLECTION: &'static str = "follows";
+
+    type SortKey = String;
+
+    fn id(&self) -> String {
+        self.uri.clone()
+    }
+
+    fn timestamp(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+
+    fn sort_key(&self) -> String {
+        self.uri.clone()
+    }
+}
+
+/// An account blocked by the owner of the archive.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BlueskyBlock {
+    /// The URI of the block record.
+    pub uri: String,
+
+    pub subject_did: String,
+    pub created_at: DateTime<Utc>,
+}
+
+record_identity!(BlueskyBlock, uri);
+
+impl Record for BlueskyBlock {
+    const COLLECTION: &'static str = "blocks";
+
+    type SortKey = String;
+
+    fn id(&self) -> String {
+        self.uri.clone()
+    }
+
+    fn timestamp(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+
+    fn sort_key(&self) -> String {
+        self.uri.clone()
+    }
+}
+
+/// A thread started by the owner of the archive, with every reply in it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BlueskyThread {
+    /// The URI of the post at the root of the thread.
+    pub uri: String,
+
+    /// When the root post was created.
+    pub created_at: DateTime<Utc>,
+
+    /// The number of direct replies to the root post when the thread was last
+    /// fetched, which tells later syncs whether it has to be fetched again.
+    pub reply_count: Option<i64>,
+
+    /// The replies in the thread, in the order they appear in it.
+    pub replies: Vec<BlueskyThreadPost>,
+}
+
+// Threads grow as people reply to them, so they're refreshed with the latest
+// replies on every sync.
+record_identity!(BlueskyThread, uri);
+
+impl Record for BlueskyThread {
+    const COLLECTION: &'static str = "threads";
+
+    type SortKey = String;
+
+    fn id(&self) -> String {
+        self.uri.clone()
+    }
+
+    fn timestamp(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+
+    fn sort_key(&self) -> String {
+        self.uri.clone()
+    }
+
+    fn refresh(&mut self, fetched: Self) -> bool {
+        let mut changed = false;
+
+        if fetched.reply_count.is_some() && self.reply_count != fetched.reply_count {
+            self.reply_count = fetched.reply_count;
+            changed = true;
+        }
+
+        // Replies that have since been deleted are kept, as that's the point
+        // of archiving them, but are marked as such.
+        let deleted_at = Utc::now();
+
+        for existing in &mut self.replies {
+            if !fetched
+                .replies
+                .iter()
+                .any(|reply| reply.post.uri == existing.post.uri)
+            {
+                changed |= existing.post.mark_deleted(deleted_at);
+            }
+        }
+
+        for reply in fetched.replies {
+            match self
+                .replies
+                .iter_mut()
+                .find(|existing| existing.post.uri == reply.post.uri)
+            {
+                Some(existing) => {
+                    if existing.author_handle != reply.author_handle {
+                        existing.author_handle = reply.author_handle;
+                        changed = true;
+                    }
+
+                    changed |= existing.post.refresh(reply.post);
+                }
+                None => {
+                    self.replies.push(reply);
+                    changed = true;
+                }
+            }
+        }
+
+        changed
+    }
+}
+
+/// A reply within a [`BlueskyThread`], along with its author.
+#[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct BlueskyThreadPost {
+    pub author_did: String,
+    pub author_handle: String,
+
+    #[serde(flatten)]
+    pub post: BlueskyPost,
+}
+
+/// The alphabet that record keys (TIDs) are encoded in.
+const TID_ALPHABET: &[u8] = b"234567abcdefghijklmnopqrstuvwxyz";
+
+/// Returns the time embedded in a TID, the timestamp-based identifier used as
+/// the key of most records, e.g. `3jzfcijpj2z2a`.
+pub fn tid_timestamp(tid: &str) -> Option<DateTime<Utc>> {
+    if tid.len() != 13 {
+        return None;
+    }
+
+    let mut value: u64 = 0;
+
+    for byte in tid.bytes() {
+        let digit = TID_ALPHABET.iter().position(|&c| c == byte)?;
+        value = (value << 5) | digit as u64;
+    }
+
+    // The lowest 10 bits are a clock identifier, and the rest are microseconds
+    // since the Unix epoch.
+    DateTime::from_timestamp_micros((value >> 10) as i64)
+}
@@ -0,0 +1,134 @@
+use chrono::{DateTime, Utc};
+use indexmap::IndexSet;
+use serde::{Deserialize, Serialize};
+
+use crate::models::Record;
+
+/// A scrobbled track.
+///
+/// The metadata after `listened_at` is only archived when it's asked for, and
+/// is left out otherwise, so that tracks keep the shape they've always had.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Track {
+    pub name: String,
+    pub artist: String,
+    pub album: String,
+    pub listened_at: DateTime<Utc>,
+
+    /// The MusicBrainz IDs of the track, its artist and its album, where
+    /// Last.fm knows them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mbid: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artist_mbid: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub album_mbid: Option<String>,
+
+    /// The pages of the track and its artist on Last.fm.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artist_url: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub streamable: Option<bool>,
+
+    /// Whether the user has loved the track, as of when it was last synced.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub loved: Option<bool>,
+
+    /// The album art, in each of the sizes Last.fm offers.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub images: Option<Vec<TrackImage>>,
+}
+
+/// A copy of a track's album art.
+#[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TrackImage {
+    /// The size of the image, e.g. `extralarge`.
+    pub size: String,
+
+    pub url: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct YearData {
+    pub tracks: IndexSet<Track>,
+}
+
+// The metadata changes over time (and is missing from older archives), so it's
+// left out. Otherwise loving a track would archive it again.
+record_identity!(Track, listened_at, artist, name);
+
+impl Record for Track {
+    const COLLECTION: &'static str = "tracks";
+
+    type SortKey = (DateTime<Utc>, String);
+
+    /// Scrobbles don't have an ID of their own, so we identify them by when they were
+    /// listened to and what was listened to.
+    fn id(&self) -> String {
+        format!(
+            "{}:{} - {}",
+            self.listened_at.timestamp(),
+            self.artist,
+            self.name
+        )
+    }
+
+    fn timestamp(&self) -> DateTime<Utc> {
+        self.listened_at
+    }
+
+    fn sort_key(&self) -> (DateTime<Utc>, String) {
+        (self.listened_at, self.name.clone())
+    }
+
+    fn refresh(&mut self, fetched: Self) -> bool {
+        let mut changed = false;
+
+        // Last.fm corrects album names over time.
+        if self.album != fetched.album {
+            self.album = fetched.album;
+            changed = true;
+        }
+
+        changed |= update(&mut self.mbid, fetched.mbid);
+        changed |= update(&mut self.artist_mbid, fetched.artist_mbid);
+        changed |= update(&mut self.album_mbid, fetched.album_mbid);
+        changed |= update(&mut self.url, fetched.url);
+        changed |= update(&mut self.artist_url, fetched.artist_url);
+        changed |= update(&mut self.streamable, fetched.streamable);
+        changed |= update(&mut self.loved, fetched.loved);
+        changed |= update(&mut self.images, fetched.images);
+
+        changed
+    }
+}
+
+/// Replaces an archived detail with its fetched value, returning whether it
+/// changed.
+///
+/// Details that are missing from the fetched copy are kept, as pages fetched
+/// without `extended=1` (or cached before it was used) don't have all of them.
+fn update<T: PartialEq>(archived: &mut Option<T>, fetched: Option<T>) -> bool {
+    if fetched.is_none() || *archived == fetched {
+        return false;
+    }
+
+    *archived = fetched;
+
+    true
+}
+
+impl From<IndexSet<Track>> for YearData {
+    fn from(tracks: IndexSet<Track>) -> Self {
+        Self { tracks }
+    }
+}
+
+impl From<YearData> for IndexSet<Track> {
+    fn from(year_data: YearData) -> Self {
+        year_data.tracks
+    }
+}
@@ -0,0 +1,572 @@
+use std::hash::{Hash, Hasher};
+
+use chrono::{DateTime, Utc};
+use indexmap::IndexSet;
+use serde::{Deserialize, Serialize};
+
+use crate::media::StoredMedia;
+use crate::models::{fill, Record};
+use crate::twitter;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Tweet {
+    pub id: u64,
+    pub created_at: DateTime<Utc>,
+    pub text: String,
+    pub entities: Option<TweetEntities>,
+    pub in_reply_to: Option<TweetReply>,
+}
+
+record_identity!(Tweet, id);
+
+impl Record for Tweet {
+    const COLLECTION: &'static str = "tweets";
+
+    type SortKey = u64;
+
+    fn id(&self) -> String {
+        self.id.to_string()
+    }
+
+    fn timestamp(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+
+    fn sort_key(&self) -> u64 {
+        self.id
+    }
+
+    fn refresh(&mut self, mut fetched: Self) -> bool {
+        let mut changed = false;
+
+        changed |= fill(&mut self.entities, &mut fetched.entities);
+        changed |= fill(&mut self.in_reply_to, &mut fetched.in_reply_to);
+
+        let fetched_media = fetched
+            .entities
+            .iter_mut()
+            .flat_map(|entities| entities.media.iter_mut().flatten());
+
+        let media = self
+            .entities
+            .iter_mut()
+            .flat_map(|entities| entities.media.iter_mut().flatten());
+
+        for (entity, fetched_entity) in media.zip(fetched_media) {
+            changed |= entity.fill_stored_media(fetched_entity);
+        }
+
+        changed
+    }
+}
+
+impl From<egg_mode::tweet::Tweet> for Tweet {
+    fn from(tweet: egg_mode::tweet::Tweet) -> Self {
+        Self {
+            id: tweet.id,
+            text: tweet.text,
+            entities: TweetEntities {
+                urls: {
+                    let urls = tweet
+                        .entities
+                        .urls
+                        .into_iter()
+                        .map(|entity| TweetUrlEntity {
+                            display_url: entity.display_url,
+                            expanded_url: entity.expanded_url,
+                            url: entity.url,
+                        })
+                        .collect::<Vec<_>>();
+
+                    if urls.is_empty() {
+                        None
+                    } else {
+                        Some(urls)
+                    }
+                },
+                media: tweet.entities.media.map(|media| {
+                    media
+                        .into_iter()
+                        .map(|entity| TweetMediaEntity {
+                            id: entity.id,
+                            r#type: entity.media_type.into(),
+                            url: entity.media_url_https,
+                            local_path: None,
+                            sha256: None,
+                        })
+                        .collect()
+                }),
+            }
+            .into_option(),
+            in_reply_to: match (
+                tweet.in_reply_to_status_id,
+                tweet.in_reply_to_user_id,
+                tweet.in_reply_to_screen_name,
+            ) {
+                (Some(status_id), Some(user_id), Some(user_name)) => Some(TweetReply {
+                    status_id,
+                    user_id,
+                    user_name,
+                }),
+                _ => None,
+            },
+            created_at: tweet.created_at,
+        }
+    }
+}
+
+impl From<twitter::ArchivedTweet> for Tweet {
+    fn from(tweet: twitter::ArchivedTweet) -> Self {
+        Self {
+            id: tweet.id,
+            text: tweet.full_text,
+            entities: TweetEntities {
+                urls: {
+                    let urls = tweet
+                        .entities
+                        .urls
+                        .into_iter()
+                        .filter_map(|entity| match entity {
+                            twitter::ArchivedTweetUrlEntity::WellFormed(entity) => {
+                                Some(TweetUrlEntity {
+                                    display_url: entity.display_url,
+                                    expanded_url: entity.expanded_url,
+                                    url: entity.url,
+                                })
+                            }
+                            twitter::ArchivedTweetUrlEntity::Malformed { .. } => None,
+                        })
+                        .collect::<Vec<_>>();
+
+                    if urls.is_empty() {
+                        None
+                    } else {
+                        Some(urls)
+                    }
+                },
+                media: tweet.entities.media.map(|media| {
+                    media
+                        .into_iter()
+                        .map(|entity| TweetMediaEntity {
+                            id: entity.id,
+                            r#type: entity.media_type.into(),
+                            url: entity.media_url_https,
+                            local_path: None,
+                            sha256: None,
+                        })
+                        .collect()
+                }),
+            }
+            .into_option(),
+            in_reply_to: match (
+                tweet.in_reply_to_status_id,
+                tweet.in_reply_to_user_id,
+                tweet.in_reply_to_screen_name,
+            ) {
+                (Some(status_id), Some(user_id), Some(user_name)) => Some(TweetReply {
+                    status_id,
+                    user_id,
+                    user_name,
+                }),
+                _ => None,
+            },
+            created_at: tweet.created_at,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TweetEntities {
+    pub urls: Option<Vec<TweetUrlEntity>>,
+    pub media: Option<Vec<TweetMediaEntity>>,
+}
+
+impl TweetEntities {
+    pub fn is_empty(&self) -> bool {
+        self.urls.is_none() && self.media.is_none()
+    }
+
+    pub fn into_option(self) -> Option<TweetEntities> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(self)
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TweetUrlEntity {
+    pub display_url: String,
+    pub expanded_url: Option<String>,
+    pub url: String,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MediaType {
+    #[serde(rename = "photo")]
+    Photo,
+
+    #[serde(rename = "video")]
+    Video,
+
+    #[serde(rename = "animated_gif")]
+    Gif,
+}
+
+impl From<egg_mode::entities::MediaType> for MediaType {
+    fn from(value: egg_mode::entities::MediaType) -> Self {
+        match value {
+            egg_mode::entities::MediaType::Photo => MediaType::Photo,
+            egg_mode::entities::MediaType::Video => MediaType::Video,
+            egg_mode::entities::MediaType::Gif => MediaType::Gif,
+        }
+    }
+}
+
+impl From<twitter::MediaType> for MediaType {
+    fn from(value: twitter::MediaType) -> Self {
+        match value {
+            twitter::MediaType::Photo => MediaType::Photo,
+            twitter::MediaType::Video => MediaType::Video,
+            twitter::MediaType::Gif => MediaType::Gif,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TweetMediaEntity {
+    pub id: u64,
+    pub r#type: MediaType,
+    pub url: String,
+
+    /// The path to the downloaded copy of the media, relative to the archive.
+    pub local_path: Option<String>,
+
+    /// The SHA-256 hash of the downloaded copy of the media.
+    pub sha256: Option<String>,
+}
+
+impl TweetMediaEntity {
+    pub fn set_stored_media(&mut self, stored_media: StoredMedia) {
+        self.local_path = Some(stored_media.path);
+        self.sha256 = Some(stored_media.sha256);
+    }
+
+    /// Takes the downloaded copy of the same media from a freshly fetched copy
+    /// of it, if this one doesn't have one yet. Returns whether it did.
+    fn fill_stored_media(&mut self, fetched: &mut Self) -> bool {
+        if self.local_path.is_some() || fetched.local_path.is_none() || self != fetched {
+            return false;
+        }
+
+        self.local_path = fetched.local_path.take();
+        self.sha256 = fetched.sha256.take();
+
+        true
+    }
+}
+
+impl PartialEq for TweetMediaEntity {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id && self.r#type == other.r#type && self.url == other.url
+    }
+}
+
+impl Eq for TweetMediaEntity {}
+
+impl Hash for TweetMediaEntity {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+        self.r#type.hash(state);
+        self.url.hash(state);
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TweetReply {
+    pub status_id: u64,
+    pub user_id: u64,
+    pub user_name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TwitterYearData {
+    pub tweets: IndexSet<Tweet>,
+}
+
+impl From<IndexSet<Tweet>> for TwitterYearData {
+    fn from(tweets: IndexSet<Tweet>) -> Self {
+        Self { tweets }
+    }
+}
+
+impl From<TwitterYearData> for IndexSet<Tweet> {
+    fn from(year_data: TwitterYearData) -> Self {
+        year_data.tweets
+    }
+}
+
+/// The time Twitter's snowflake IDs are counted from, in milliseconds since the Unix epoch.
+const TWITTER_EPOCH_MS: i64 = 1_288_834_974_657;
+
+/// Returns the time embedded in a Twitter snowflake ID.
+///
+/// IDs from before snowflakes were introduced (late 2010) resolve to the Twitter epoch.
+pub fn snowflake_timestamp(id: u64) -> DateTime<Utc> {
+    DateTime::from_timestamp_millis(TWITTER_EPOCH_MS + (id >> 22) as i64).unwrap_or_default()
+}
+
+/// A tweet liked by the owner of the archive.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Like {
+    pub tweet_id: u64,
+    pub text: Option<String>,
+    pub url: Option<String>,
+}
+
+record_identity!(Like, tweet_id);
+
+impl Record for Like {
+    const COLLECTION: &'static str = "likes";
+
+    type SortKey = u64;
+
+    fn id(&self) -> String {
+        self.tweet_id.to_string()
+    }
+
+    /// The archive doesn't record when tweets were liked, so likes are
+    /// timestamped with when the liked tweet was posted.
+    fn timestamp(&self) -> DateTime<Utc> {
+        snowflake_timestamp(self.tweet_id)
+    }
+
+    fn sort_key(&self) -> u64 {
+        self.tweet_id
+    }
+
+    fn refresh(&mut self, mut fetched: Self) -> bool {
+        fill(&mut self.text, &mut fetched.text) | fill(&mut self.url, &mut fetched.url)
+    }
+}
+
+impl From<twitter::ArchivedLike> for Like {
+    fn from(like: twitter::ArchivedLike) -> Self {
+        Self {
+            tweet_id: like.tweet_id,
+            text: like.full_text,
+            url: like.expanded_url,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DirectMessage {
+    pub id: u64,
+    pub conversation_id: String,
+    pub sender_id: u64,
+    pub recipient_id: Option<u64>,
+    pub created_at: DateTime<Utc>,
+    pub text: String,
+    pub media_urls: Vec<String>,
+}
+
+record_identity!(DirectMessage, id);
+
+impl Record for DirectMessage {
+    const COLLECTION: &'static str = "messages";
+
+    type SortKey = u64;
+
+    fn id(&self) -> String {
+        self.id.to_string()
+    }
+
+    fn timestamp(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+
+    fn sort_key(&self) -> u64 {
+        self.id
+    }
+}
+
+impl DirectMessage {
+    pub fn from_archived(conversation_id: String, message: twitter::ArchivedDirectMessage) -> Self {
+        Self {
+            id: message.id,
+            conversation_id,
+            sender_id: message.sender_id,
+            recipient_id: message.recipient_id,
+            created_at: message.created_at,
+            text: message.text,
+            media_urls: message.media_urls,
+        }
+    }
+}
+
+/// An account that follows the owner of the archive.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Follower {
+    pub account_id: u64,
+    pub user_link: String,
+}
+
+/// An account that the owner of the archive follows.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Following {
+    pub account_id: u64,
+    pub user_link: String,
+}
+
+record_identity!(Follower, account_id);
+record_identity!(Following, account_id);
+
+impl Record for Follower {
+    const COLLECTION: &'static str = "followers";
+
+    type SortKey = u64;
+
+    fn id(&self) -> String {
+        self.account_id.to_string()
+    }
+
+    /// The archive doesn't record when accounts were followed, so all follows
+    /// share the same timestamp and are best stored in a single partition.
+    fn timestamp(&self) -> DateTime<Utc> {
+        DateTime::UNIX_EPOCH
+    }
+
+    fn sort_key(&self) -> u64 {
+        self.account_id
+    }
+}
+
+impl Record for Following {
+    const COLLECTION: &'static str = "following";
+
+    type SortKey = u64;
+
+    fn id(&self) -> String {
+        self.account_id.to_string()
+    }
+
+    /// See [`Follower::timestamp`].
+    fn timestamp(&self) -> DateTime<Utc> {
+        DateTime::UNIX_EPOCH
+    }
+
+    fn sort_key(&self) -> u64 {
+        self.account_id
+    }
+}
+
+impl From<twitter::ArchivedConnection> for Follower {
+    fn from(connection: twitter::ArchivedConnection) -> Self {
+        Self {
+            account_id: connection.account_id,
+            user_link: connection.user_link,
+        }
+    }
+}
+
+impl From<twitter::ArchivedConnection> for Following {
+    fn from(connection: twitter::ArchivedConnection) -> Self {
+        Self {
+            account_id: connection.account_id,
+            user_link: connection.user_link,
+        }
+    }
+}
+
+/// The account and profile of the owner of the archive.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TwitterProfile {
+    pub account_id: u64,
+    pub username: String,
+    pub display_name: String,
+    pub email: Option<String>,
+    pub created_via: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub bio: Option<String>,
+    pub website: Option<String>,
+    pub location: Option<String>,
+    pub avatar_url: Option<String>,
+    pub header_url: Option<String>,
+}
+
+record_identity!(TwitterProfile, account_id);
+
+impl Record for TwitterProfile {
+    const COLLECTION: &'static str = "profile";
+
+    type SortKey = u64;
+
+    fn id(&self) -> String {
+        self.account_id.to_string()
+    }
+
+    fn timestamp(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+
+    fn sort_key(&self) -> u64 {
+        self.account_id
+    }
+
+    /// A profile is a snapshot, so the most recently imported one wins.
+    fn refresh(&mut self, fetched: Self) -> bool {
+        let changed = self.username != fetched.username
+            || self.display_name != fetched.display_name
+            || self.email != fetched.email
+            || self.created_via != fetched.created_via
+            || self.created_at != fetched.created_at
+            || self.bio != fetched.bio
+            || self.website != fetched.website
+            || self.location != fetched.location
+            || self.avatar_url != fetched.avatar_url
+            || self.header_url != fetched.header_url;
+
+        if changed {
+            *self = fetched;
+        }
+
+        changed
+    }
+}
+
+impl TwitterProfile {
+    pub fn from_archived(
+        account: twitter::ArchivedAccount,
+        profile: Option<twitter::ArchivedProfile>,
+    ) -> Self {
+        let (description, avatar_url, header_url) = match profile {
+            Some(profile) => (
+                Some(profile.description),
+                profile.avatar_media_url,
+                profile.header_media_url,
+            ),
+            None => (None, None, None),
+        };
+
+        let (bio, website, location) = match description {
+            Some(description) => (description.bio, description.website, description.location),
+            None => (None, None, None),
+        };
+
+        Self {
+            account_id: account.account_id,
+            username: account.username,
+            display_name: account.account_display_name,
+            email: account.email,
+            created_via: account.created_via,
+            created_at: account.created_at,
+            bio,
+            website,
+            location,
+            avatar_url,
+            header_url,
+        }
+    }
+}
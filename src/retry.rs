@@ -0,0 +1,188 @@
+use std::future::Future;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use rand::Rng;
+
+use crate::PluckError;
+
+/// The policy for retrying requests that failed with a transient error.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// The maximum number of attempts to make, including the first one.
+    pub max_attempts: u32,
+
+    /// The delay before the first retry, which doubles with every subsequent retry.
+    pub initial_backoff: Duration,
+
+    /// The upper bound on the delay between retries.
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Runs the given operation, retrying it for as long as it fails with a
+    /// [retryable](PluckError::is_retryable) error and attempts remain.
+    pub async fn retry<T, F, Fut>(&self, mut operation: F) -> Result<T, PluckError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, PluckError>>,
+    {
+        let mut attempt = 1;
+
+        loop {
+            match operation().await {
+                Ok(value) => return Ok(value),
+                Err(err) if err.is_retryable() && attempt < self.max_attempts => {
+                    let delay = err.retry_after().unwrap_or_else(|| self.backoff(attempt));
+
+                    eprintln!(
+                        "Attempt {} of {} failed ({}), retrying in {:.1}s",
+                        attempt,
+                        self.max_attempts,
+                        err,
+                        delay.as_secs_f64()
+                    );
+
+                    tokio::time::sleep(delay).await;
+
+                    attempt += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Returns the jittered delay to wait after the given (1-based) attempt failed.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt - 1))
+            .min(self.max_backoff);
+
+        // Wait somewhere between half and all of the backoff, so that concurrent
+        // clients don't retry in lockstep.
+        backoff.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+    }
+}
+
+/// Parses the value of a `Retry-After` header, which is either a number of
+/// seconds or an HTTP date.
+pub(crate) fn parse_retry_after(value: &str) -> Option<Duration> {
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let retry_at = DateTime::parse_from_rfc2822(value.trim()).ok()?;
+
+    (retry_at.with_timezone(&Utc) - Utc::now()).to_std().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    #[test]
+    fn retry_after_can_be_a_number_of_seconds() {
+        assert_eq!(parse_retry_after("120"), Some(Duration::from_secs(120)));
+        assert_eq!(parse_retry_after(" 5 "), Some(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn retry_after_can_be_an_http_date() {
+        let retry_at = Utc::now() + chrono::Duration::minutes(10);
+        let delay = parse_retry_after(&retry_at.to_rfc2822()).unwrap();
+
+        assert!(delay > Duration::from_secs(9 * 60) && delay <= Duration::from_secs(10 * 60));
+    }
+
+    #[test]
+    fn retry_after_is_ignored_when_past_or_invalid() {
+        let retry_at = Utc::now() - chrono::Duration::minutes(10);
+
+        assert_eq!(parse_retry_after(&retry_at.to_rfc2822()), None);
+        assert_eq!(parse_retry_after("soon"), None);
+        assert_eq!(parse_retry_after("-1"), None);
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        let policy = RetryPolicy::default();
+
+        for (attempt, full_backoff) in [(1, 1), (2, 2), (3, 4), (10, 60), (40, 60)] {
+            let backoff = policy.backoff(attempt);
+            let full_backoff = Duration::from_secs(full_backoff);
+
+            assert!(backoff >= full_backoff / 2 && backoff <= full_backoff);
+        }
+    }
+
+    #[tokio::test]
+    async fn only_retryable_errors_are_retried() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+        };
+
+        let server_error = || PluckError::BlueskyApi {
+            status: 503,
+            message: "Service Unavailable".to_string(),
+            retry_after: None,
+        };
+
+        let attempts = Cell::new(0);
+        let result = policy
+            .retry(|| async {
+                attempts.set(attempts.get() + 1);
+
+                match attempts.get() {
+                    1 => Err(server_error()),
+                    _ => Ok(()),
+                }
+            })
+            .await;
+
+        assert!(result.is_ok());
+        assert_eq!(attempts.get(), 2);
+
+        let attempts = Cell::new(0);
+        let result = policy
+            .retry(|| async {
+                attempts.set(attempts.get() + 1);
+
+                Err::<(), _>(server_error())
+            })
+            .await;
+
+        assert!(result.is_err());
+        assert_eq!(attempts.get(), 3);
+
+        let attempts = Cell::new(0);
+        let result = policy
+            .retry(|| async {
+                attempts.set(attempts.get() + 1);
+
+                Err::<(), _>(PluckError::BlueskyApi {
+                    status: 400,
+                    message: "Bad Request".to_string(),
+                    retry_after: None,
+                })
+            })
+            .await;
+
+        assert!(result.is_err());
+        assert_eq!(attempts.get(), 1);
+    }
+}
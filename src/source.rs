@@ -0,0 +1,65 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::models::Record;
+use crate::PluckError;
+
+/// A page of items fetched from a [`Source`].
+pub struct Page<T, C> {
+    pub items: Vec<T>,
+
+    /// The cursor to fetch the next page with, or `None` if this was the last page.
+    pub next_cursor: Option<C>,
+
+    /// The timestamp of the newest entry on the page, counting the entries that
+    /// the source filtered out, for sources that don't return every entry as an
+    /// item. Otherwise the newest of the items' timestamps is used.
+    pub newest_timestamp: Option<DateTime<Utc>>,
+}
+
+/// A source of items that can be synced into an archive.
+#[async_trait]
+pub trait Source: Send + Sync {
+    /// The item fetched from this source.
+    type Item: Record;
+
+    /// The cursor used to page through this source.
+    ///
+    /// Cursors are persisted in checkpoints so that interrupted syncs can be resumed.
+    type Cursor: Serialize + DeserializeOwned + Send + Sync;
+
+    /// Whether this source returns items newest-first, which lets an incremental
+    /// sync stop once it reaches items older than the [`HighWaterMark`].
+    ///
+    /// Sources that aren't ordered by recency, such as a downloaded archive or
+    /// repository, are imported in their entirety instead.
+    ///
+    /// [`HighWaterMark`]: crate::checkpoint::HighWaterMark
+    fn supports_incremental_sync(&self) -> bool {
+        true
+    }
+
+    /// Whether this source returns every item that still exists upstream,
+    /// newest first.
+    ///
+    /// If so, archived items that fall between the oldest and newest items
+    /// fetched by a sync, but weren't fetched themselves, are marked as deleted.
+    fn tracks_deletions(&self) -> bool {
+        false
+    }
+
+    /// Fetches the page of items at the given cursor, or the first page if no cursor is given.
+    async fn fetch_page(
+        &mut self,
+        cursor: Option<Self::Cursor>,
+    ) -> Result<Page<Self::Item, Self::Cursor>, PluckError>;
+
+    /// Pauses between pages to avoid hammering the source.
+    async fn pause(&self, _pages_fetched: usize) {
+        tokio::time::sleep(Duration::from_millis(1000)).await;
+    }
+}
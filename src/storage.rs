@@ -0,0 +1,549 @@
+mod file;
+mod partition;
+mod sqlite;
+
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use indexmap::IndexSet;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+
+use crate::models::Record;
+use crate::PluckError;
+
+pub use file::*;
+pub use partition::*;
+pub use sqlite::*;
+
+/// A backend that archived records are stored in.
+///
+/// Records are grouped into partitions according to the storage's
+/// [`Partitioning`], and each [`Record::COLLECTION`] is stored separately. Storages also hold the bookkeeping state for syncs, such as
+/// checkpoints and high-water marks.
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// Returns how records are partitioned in this storage.
+    fn partitioning(&self) -> Partitioning;
+
+    /// Returns the partitions that have records archived, in ascending order.
+    async fn partitions<T: Record>(&self) -> Result<Vec<Partition>, PluckError>;
+
+    /// Returns the records archived for the given partition, if there are any.
+    async fn read_partition<T: Record>(
+        &self,
+        partition: Partition,
+    ) -> Result<Option<IndexSet<T>>, PluckError>;
+
+    /// Replaces the records archived for the given partition.
+    async fn write_partition<T: Record>(
+        &self,
+        partition: Partition,
+        records: &IndexSet<T>,
+    ) -> Result<(), PluckError>;
+
+    /// Returns the records archived for the most recent partition, if there are any.
+    async fn latest_partition<T: Record>(
+        &self,
+    ) -> Result<Option<(Partition, IndexSet<T>)>, PluckError> {
+        let Some(partition) = self.partitions::<T>().await?.pop() else {
+            return Ok(None);
+        };
+
+        Ok(self
+            .read_partition(partition)
+            .await?
+            .map(|records| (partition, records)))
+    }
+
+    /// Returns whether any `T` records are archived in this storage.
+    ///
+    /// Unlike reading them, this doesn't fail when the storage holds a different
+    /// collection instead, so it can be used to find out what an archive holds.
+    /// The records have to have the shape of a `T`, not just be stored under its
+    /// [`Record::COLLECTION`], since different kinds of records can share a name
+    /// (e.g. Bluesky and Twitter likes).
+    async fn contains<T: Record>(&self) -> Result<bool, PluckError>;
+
+    /// Reads the sync state stored under the given key for a collection.
+    async fn read_state<S: DeserializeOwned>(
+        &self,
+        collection: &str,
+        key: &str,
+    ) -> Result<Option<S>, PluckError>;
+
+    /// Stores the sync state under the given key for a collection.
+    async fn write_state<S: Serialize + Sync>(
+        &self,
+        collection: &str,
+        key: &str,
+        state: &S,
+    ) -> Result<(), PluckError>;
+
+    /// Removes the sync state stored under the given key for a collection.
+    async fn clear_state(&self, collection: &str, key: &str) -> Result<(), PluckError>;
+}
+
+/// The format an archive is stored in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageFormat {
+    /// One TOML file per partition.
+    #[default]
+    Toml,
+
+    /// One JSON file per partition.
+    Json,
+
+    /// One JSON Lines file per partition.
+    Jsonl,
+
+    /// A single SQLite database (`pluck.sqlite`).
+    Sqlite,
+}
+
+impl StorageFormat {
+    /// Opens the storage for an archive in this format in the given directory.
+    pub fn open(
+        &self,
+        dir: &Path,
+        partitioning: Partitioning,
+        keep_backups: bool,
+    ) -> Result<AnyStorage, PluckError> {
+        let file_format = match self {
+            Self::Toml => FileFormat::Toml,
+            Self::Json => FileFormat::Json,
+            Self::Jsonl => FileFormat::JsonLines,
+            Self::Sqlite => {
+                let mut storage = SqliteStorage::open(dir.join("pluck.sqlite"))?;
+                storage.partition_by(partitioning);
+
+                return Ok(storage.into());
+            }
+        };
+
+        let mut storage = FileStorage::new(dir, file_format);
+        storage.partition_by(partitioning);
+
+        if keep_backups {
+            storage.keep_backups();
+        }
+
+        Ok(storage.into())
+    }
+
+    /// Opens the storage for an existing archive in this format in the given
+    /// directory, without writing anything to it.
+    pub fn open_read_only(
+        &self,
+        dir: &Path,
+        partitioning: Partitioning,
+    ) -> Result<AnyStorage, PluckError> {
+        let file_format = match self {
+            Self::Toml => FileFormat::Toml,
+            Self::Json => FileFormat::Json,
+            Self::Jsonl => FileFormat::JsonLines,
+            Self::Sqlite => {
+                let mut storage = SqliteStorage::open_read_only(dir.join("pluck.sqlite"))?;
+                storage.partition_by(partitioning);
+
+                return Ok(storage.into());
+            }
+        };
+
+        let mut storage = FileStorage::new(dir, file_format);
+        storage.partition_by(partitioning);
+
+        Ok(storage.into())
+    }
+}
+
+/// Any of the supported storage backends, for when the backend is chosen at runtime.
+pub enum AnyStorage {
+    File(FileStorage),
+    Sqlite(SqliteStorage),
+}
+
+impl AnyStorage {
+    /// Returns the path to the directory or database that records are stored in.
+    pub fn location(&self) -> &Path {
+        match self {
+            Self::File(storage) => storage.dir(),
+            Self::Sqlite(storage) => storage.path(),
+        }
+    }
+}
+
+impl From<FileStorage> for AnyStorage {
+    fn from(storage: FileStorage) -> Self {
+        Self::File(storage)
+    }
+}
+
+impl From<SqliteStorage> for AnyStorage {
+    fn from(storage: SqliteStorage) -> Self {
+        Self::Sqlite(storage)
+    }
+}
+
+#[async_trait]
+impl Storage for AnyStorage {
+    fn partitioning(&self) -> Partitioning {
+        match self {
+            Self::File(storage) => storage.partitioning(),
+            Self::Sqlite(storage) => storage.partitioning(),
+        }
+    }
+
+    async fn partitions<T: Record>(&self) -> Result<Vec<Partition>, PluckError> {
+        match self {
+            Self::File(storage) => storage.partitions::<T>().await,
+            Self::Sqlite(storage) => storage.partitions::<T>().await,
+        }
+    }
+
+    async fn read_partition<T: Record>(
+        &self,
+        partition: Partition,
+    ) -> Result<Option<IndexSet<T>>, PluckError> {
+        match self {
+            Self::File(storage) => storage.read_partition(partition).await,
+            Self::Sqlite(storage) => storage.read_partition(partition).await,
+        }
+    }
+
+    async fn write_partition<T: Record>(
+        &self,
+        partition: Partition,
+        records: &IndexSet<T>,
+    ) -> Result<(), PluckError> {
+        match self {
+            Self::File(storage) => storage.write_partition(partition, records).await,
+            Self::Sqlite(storage) => storage.write_partition(partition, records).await,
+        }
+    }
+
+    async fn contains<T: Record>(&self) -> Result<bool, PluckError> {
+        match self {
+            Self::File(storage) => storage.contains::<T>().await,
+            Self::Sqlite(storage) => storage.contains::<T>().await,
+        }
+    }
+
+    async fn read_state<S: DeserializeOwned>(
+        &self,
+        collection: &str,
+        key: &str,
+    ) -> Result<Option<S>, PluckError> {
+        match self {
+            Self::File(storage) => storage.read_state(collection, key).await,
+            Self::Sqlite(storage) => storage.read_state(collection, key).await,
+        }
+    }
+
+    async fn write_state<S: Serialize + Sync>(
+        &self,
+        collection: &str,
+        key: &str,
+        state: &S,
+    ) -> Result<(), PluckError> {
+        match self {
+            Self::File(storage) => storage.write_state(collection, key, state).await,
+            Self::Sqlite(storage) => storage.write_state(collection, key, state).await,
+        }
+    }
+
+    async fn clear_state(&self, collection: &str, key: &str) -> Result<(), PluckError> {
+        match self {
+            Self::File(storage) => storage.clear_state(collection, key).await,
+            Self::Sqlite(storage) => storage.clear_state(collection, key).await,
+        }
+    }
+}
+
+/// Reads a JSON file, returning `None` if it doesn't exist.
+pub(crate) async fn read_json<T: DeserializeOwned>(
+    filepath: &Path,
+) -> Result<Option<T>, PluckError> {
+    let contents = match tokio::fs::read_to_string(filepath).await {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(PluckError::storage(filepath, err)),
+    };
+
+    serde_json::from_str(&contents)
+        .map(Some)
+        .map_err(|err| PluckError::deserialize(filepath.display().to_string(), err))
+}
+
+/// Atomically writes a value to a JSON file.
+pub(crate) async fn write_json<T: Serialize>(filepath: &Path, value: &T) -> Result<(), PluckError> {
+    let contents =
+        serde_json::to_string_pretty(value).map_err(|err| PluckError::storage(filepath, err))?;
+
+    write_atomic(filepath, contents.as_bytes(), false).await
+}
+
+/// Atomically replaces the contents of the file at the given path.
+///
+/// The contents are written to a temporary file in the same directory, which is
+/// flushed to disk and then renamed over the original. This way the file either
+/// has its old contents or its new contents, even if we crash or run out of disk
+/// space partway through.
+pub async fn write_atomic(
+    filepath: &Path,
+    contents: &[u8],
+    keep_backup: bool,
+) -> Result<(), PluckError> {
+    replace_file(filepath, contents, keep_backup, false).await
+}
+
+/// Atomically replaces the contents of the file at the given path, like
+/// [`write_atomic`], with a file that only its owner can read or write.
+///
+/// The temporary file is created with those permissions before anything is
+/// written to it, so the contents (such as credentials) are never exposed.
+pub async fn write_private(filepath: &Path, contents: &[u8]) -> Result<(), PluckError> {
+    replace_file(filepath, contents, false, true).await
+}
+
+async fn replace_file(
+    filepath: &Path,
+    contents: &[u8],
+    keep_backup: bool,
+    private: bool,
+) -> Result<(), PluckError> {
+    let temp_filepath = with_appended_extension(filepath, "tmp");
+
+    let write_temp_file = async {
+        let mut options = OpenOptions::new();
+        options.write(true);
+
+        if private {
+            // The permissions only apply to new files, so one left over from an
+            // earlier run can't be reused.
+            match tokio::fs::remove_file(&temp_filepath).await {
+                Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err),
+                _ => {}
+            }
+
+            options.create_new(true);
+
+            #[cfg(unix)]
+            options.mode(0o600);
+        } else {
+            options.create(true).truncate(true);
+        }
+
+        let mut temp_file = options.open(&temp_filepath).await?;
+        temp_file.write_all(contents).await?;
+        temp_file.sync_all().await
+    };
+
+    if let Err(err) = write_temp_file.await {
+        let _ = tokio::fs::remove_file(&temp_filepath).await;
+
+        return Err(PluckError::storage(&temp_filepath, err));
+    }
+
+    if keep_backup && filepath.exists() {
+        let backup_filepath = with_appended_extension(filepath, "bak");
+
+        tokio::fs::copy(filepath, &backup_filepath)
+            .await
+            .map_err(|err| PluckError::storage(&backup_filepath, err))?;
+    }
+
+    tokio::fs::rename(&temp_filepath, filepath)
+        .await
+        .map_err(|err| PluckError::storage(filepath, err))?;
+
+    // Make sure the rename itself is durable.
+    #[cfg(unix)]
+    if let Some(parent_dir) = filepath.parent() {
+        let parent_dir = if parent_dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            parent_dir
+        };
+
+        let dir = File::open(parent_dir)
+            .await
+            .map_err(|err| PluckError::storage(parent_dir, err))?;
+
+        dir.sync_all()
+            .await
+            .map_err(|err| PluckError::storage(parent_dir, err))?;
+    }
+
+    Ok(())
+}
+
+/// Returns the given path with an extra extension appended, e.g. `2024.toml.tmp`.
+fn with_appended_extension(filepath: &Path, extension: &str) -> PathBuf {
+    let mut filepath = filepath.as_os_str().to_owned();
+    filepath.push(".");
+    filepath.push(extension);
+
+    PathBuf::from(filepath)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::*;
+    use crate::models::Track;
+
+    fn track(name: &str, month: u32) -> Track {
+        Track {
+            name: name.to_string(),
+            artist: "Artist".to_string(),
+            album: "Album".to_string(),
+            listened_at: Utc.with_ymd_and_hms(2024, month, 1, 12, 0, 0).unwrap(),
+            mbid: None,
+            artist_mbid: None,
+            album_mbid: None,
+            url: None,
+            artist_url: None,
+            streamable: None,
+            loved: None,
+            images: None,
+        }
+    }
+
+    fn names(tracks: &IndexSet<Track>) -> Vec<&str> {
+        tracks.iter().map(|track| track.name.as_str()).collect()
+    }
+
+    /// Returns an empty directory for a test to write to.
+    fn empty_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("pluck-{}-{}", name, std::process::id()));
+
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    #[tokio::test]
+    async fn records_round_trip_through_every_format() {
+        let march = Partition::Month {
+            year: 2024,
+            month: 3,
+        };
+        let april = Partition::Month {
+            year: 2024,
+            month: 4,
+        };
+
+        for format in [
+            StorageFormat::Toml,
+            StorageFormat::Json,
+            StorageFormat::Jsonl,
+            StorageFormat::Sqlite,
+        ] {
+            let dir = empty_dir(&format!("round-trip-{:?}", format));
+            let storage = format.open(&dir, Partitioning::Month, false).unwrap();
+
+            assert!(!storage.contains::<Track>().await.unwrap());
+            assert!(storage
+                .read_partition::<Track>(march)
+                .await
+                .unwrap()
+                .is_none());
+
+            let march_tracks = IndexSet::from([track("b", 3), track("a", 3)]);
+            let april_tracks = IndexSet::from([track("c", 4)]);
+
+            storage.write_partition(march, &march_tracks).await.unwrap();
+            storage.write_partition(april, &april_tracks).await.unwrap();
+
+            assert_eq!(
+                storage.partitions::<Track>().await.unwrap(),
+                vec![march, april]
+            );
+            assert!(storage.contains::<Track>().await.unwrap());
+
+            let read = storage.read_partition::<Track>(march).await.unwrap();
+            assert_eq!(names(&read.unwrap()), vec!["b", "a"], "{:?}", format);
+
+            let (partition, latest) = storage.latest_partition::<Track>().await.unwrap().unwrap();
+            assert_eq!(partition, april);
+            assert_eq!(names(&latest), vec!["c"]);
+
+            std::fs::remove_dir_all(dir).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn writing_a_partition_leaves_the_others_alone() {
+        let march = Partition::Month {
+            year: 2024,
+            month: 3,
+        };
+        let april = Partition::Month {
+            year: 2024,
+            month: 4,
+        };
+
+        for format in [StorageFormat::Toml, StorageFormat::Sqlite] {
+            let dir = empty_dir(&format!("rewrite-{:?}", format));
+            let storage = format.open(&dir, Partitioning::Month, false).unwrap();
+
+            // The same record can be archived in more than one partition.
+            let tracks = IndexSet::from([track("a", 3)]);
+            storage.write_partition(march, &tracks).await.unwrap();
+            storage.write_partition(april, &tracks).await.unwrap();
+
+            storage
+                .write_partition(march, &IndexSet::from([track("b", 3)]))
+                .await
+                .unwrap();
+
+            let read = |partition| storage.read_partition::<Track>(partition);
+            assert_eq!(names(&read(march).await.unwrap().unwrap()), vec!["b"]);
+            assert_eq!(names(&read(april).await.unwrap().unwrap()), vec!["a"]);
+
+            std::fs::remove_dir_all(dir).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn atomic_writes_replace_the_file_and_can_keep_a_backup() {
+        let dir = empty_dir("atomic-write");
+        let filepath = dir.join("2024.toml");
+
+        write_atomic(&filepath, b"first", false).await.unwrap();
+        write_atomic(&filepath, b"second", true).await.unwrap();
+
+        assert_eq!(std::fs::read_to_string(&filepath).unwrap(), "second");
+        assert_eq!(
+            std::fs::read_to_string(dir.join("2024.toml.bak")).unwrap(),
+            "first"
+        );
+        assert!(!dir.join("2024.toml.tmp").exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn sync_state_is_stored_per_collection() {
+        for format in [StorageFormat::Json, StorageFormat::Sqlite] {
+            let dir = empty_dir(&format!("state-{:?}", format));
+            let storage = format.open(&dir, Partitioning::Year, false).unwrap();
+
+            storage.write_state("posts", "cursor", &1).await.unwrap();
+            storage.write_state("likes", "cursor", &2).await.unwrap();
+            storage.clear_state("posts", "cursor").await.unwrap();
+
+            let read = |collection| storage.read_state::<u32>(collection, "cursor");
+            assert_eq!(read("posts").await.unwrap(), None);
+            assert_eq!(read("likes").await.unwrap(), Some(2));
+
+            std::fs::remove_dir_all(dir).unwrap();
+        }
+    }
+}
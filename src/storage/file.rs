@@ -0,0 +1,377 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use indexmap::IndexSet;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::models::Record;
+use crate::storage::{read_json, write_atomic, write_json, Partition, Partitioning, Storage};
+use crate::{PluckError, StorageError};
+
+/// The format of the year files written by a [`FileStorage`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileFormat {
+    /// Pretty-printed TOML, e.g. `2024.toml`.
+    Toml,
+
+    /// Pretty-printed JSON, e.g. `2024.json`.
+    Json,
+
+    /// JSON Lines with one record per line, e.g. `2024.jsonl`.
+    JsonLines,
+}
+
+impl FileFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Toml => "toml",
+            Self::Json => "json",
+            Self::JsonLines => "jsonl",
+        }
+    }
+}
+
+/// Stores records in a directory, with one file per partition.
+pub struct FileStorage {
+    dir: PathBuf,
+    format: FileFormat,
+    partitioning: Partitioning,
+    keep_backups: bool,
+}
+
+impl FileStorage {
+    pub fn new(dir: impl Into<PathBuf>, format: FileFormat) -> Self {
+        Self {
+            dir: dir.into(),
+            format,
+            partitioning: Partitioning::default(),
+            keep_backups: false,
+        }
+    }
+
+    pub fn partition_by(&mut self, partitioning: Partitioning) -> &mut Self {
+        self.partitioning = partitioning;
+        self
+    }
+
+    /// Keep the previous version of each rewritten file as a `.bak` file.
+    pub fn keep_backups(&mut self) -> &mut Self {
+        self.keep_backups = true;
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn partition_filepath<T: Record>(&self, partition: Partition) -> PathBuf {
+        let extension = self.format.extension();
+
+        match partition {
+            Partition::All => self.dir.join(format!("{}.{}", T::COLLECTION, extension)),
+            Partition::Year(year) => self.dir.join(format!("{}.{}", year, extension)),
+            Partition::Month { year, month } => self
+                .dir
+                .join(year.to_string())
+                .join(format!("{:02}.{}", month, extension)),
+            Partition::Day(date) => self
+                .dir
+                .join(date.format("%Y").to_string())
+                .join(date.format("%m").to_string())
+                .join(format!("{}.{}", date.format("%d"), extension)),
+        }
+    }
+
+    /// Returns the numbered entries in the given directory, such as `2024.toml`
+    /// or `03/`, along with their numbers.
+    ///
+    /// Entries without a numeric name are skipped, such as a config file or a
+    /// file left over from partitioning the archive differently.
+    fn numbered_entries(&self, dir: &Path, dirs: bool) -> Result<Vec<(i32, PathBuf)>, PluckError> {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(PluckError::storage(dir, err)),
+        };
+
+        let mut numbered_entries = Vec::new();
+
+        for entry in entries {
+            let entry = entry.map_err(|err| PluckError::storage(dir, err))?;
+
+            let path = entry.path();
+
+            let name = if dirs {
+                if !path.is_dir() {
+                    continue;
+                }
+
+                path.file_name()
+            } else {
+                if !path.is_file()
+                    || path.extension().and_then(|extension| extension.to_str())
+                        != Some(self.format.extension())
+                {
+                    continue;
+                }
+
+                path.file_stem()
+            };
+
+            let Some(name) = name.and_then(|name| name.to_str()) else {
+                continue;
+            };
+
+            // Skip over our own dotfiles, like the sync state.
+            if name.starts_with('.') {
+                continue;
+            }
+
+            if let Ok(number) = name.parse() {
+                numbered_entries.push((number, path));
+            }
+        }
+
+        numbered_entries.sort_unstable();
+
+        Ok(numbered_entries)
+    }
+
+    fn state_filepath(&self, collection: &str, key: &str) -> PathBuf {
+        self.dir.join(format!(".{}.{}.json", collection, key))
+    }
+
+    fn deserialize<T: Record>(
+        &self,
+        filepath: &Path,
+        contents: &str,
+    ) -> Result<IndexSet<T>, PluckError> {
+        match self.format {
+            FileFormat::Toml => {
+                let mut collections: BTreeMap<String, IndexSet<T>> =
+                    toml::from_str(contents).map_err(|err| PluckError::storage(filepath, err))?;
+
+                Ok(collections.remove(T::COLLECTION).unwrap_or_default())
+            }
+            FileFormat::Json => {
+                let mut collections: BTreeMap<String, IndexSet<T>> = serde_json::from_str(contents)
+                    .map_err(|err| PluckError::storage(filepath, err))?;
+
+                Ok(collections.remove(T::COLLECTION).unwrap_or_default())
+            }
+            FileFormat::JsonLines => contents
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(|line| {
+                    serde_json::from_str(line).map_err(|err| PluckError::storage(filepath, err))
+                })
+                .collect(),
+        }
+    }
+
+    fn serialize<T: Record>(
+        &self,
+        filepath: &Path,
+        records: &IndexSet<T>,
+    ) -> Result<String, PluckError> {
+        // Records are stored under the name of their collection (e.g. `tracks = [...]`),
+        // which is the same shape as `YearData`, `BlueskyYearData`, and `TwitterYearData`.
+        let collections = BTreeMap::from([(T::COLLECTION, records)]);
+
+        match self.format {
+            FileFormat::Toml => toml::to_string_pretty(&collections)
+                .map_err(|err| PluckError::storage(filepath, err)),
+            FileFormat::Json => serde_json::to_string_pretty(&collections)
+                .map_err(|err| PluckError::storage(filepath, err)),
+            FileFormat::JsonLines => {
+                let mut contents = String::new();
+
+                for record in records {
+                    contents.push_str(
+                        &serde_json::to_string(record)
+                            .map_err(|err| PluckError::storage(filepath, err))?,
+                    );
+                    contents.push('\n');
+                }
+
+                Ok(contents)
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Storage for FileStorage {
+    fn partitioning(&self) -> Partitioning {
+        self.partitioning
+    }
+
+    async fn partitions<T: Record>(&self) -> Result<Vec<Partition>, PluckError> {
+        let invalid_file_name =
+            |path: &Path| PluckError::storage(path, StorageError::InvalidFileName);
+
+        let mut partitions = Vec::new();
+
+        match self.partitioning {
+            Partitioning::Single => {
+                if self.partition_filepath::<T>(Partition::All).is_file() {
+                    partitions.push(Partition::All);
+                }
+            }
+            Partitioning::Year => {
+                for (year, _) in self.numbered_entries(&self.dir, false)? {
+                    partitions.push(Partition::Year(year));
+                }
+            }
+            Partitioning::Month => {
+                for (year, year_dir) in self.numbered_entries(&self.dir, true)? {
+                    for (month, path) in self.numbered_entries(&year_dir, false)? {
+                        if !(1..=12).contains(&month) {
+                            return Err(invalid_file_name(&path));
+                        }
+
+                        partitions.push(Partition::Month {
+                            year,
+                            month: month as u32,
+                        });
+                    }
+                }
+            }
+            Partitioning::Day => {
+                for (year, year_dir) in self.numbered_entries(&self.dir, true)? {
+                    for (month, month_dir) in self.numbered_entries(&year_dir, true)? {
+                        for (day, path) in self.numbered_entries(&month_dir, false)? {
+                            let date = NaiveDate::from_ymd_opt(year, month as u32, day as u32)
+                                .ok_or_else(|| invalid_file_name(&path))?;
+
+                            partitions.push(Partition::Day(date));
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(partitions)
+    }
+
+    async fn read_partition<T: Record>(
+        &self,
+        partition: Partition,
+    ) -> Result<Option<IndexSet<T>>, PluckError> {
+        let filepath = self.partition_filepath::<T>(partition);
+
+        let contents = match tokio::fs::read_to_string(&filepath).await {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(PluckError::storage(filepath, err)),
+        };
+
+        self.deserialize(&filepath, &contents).map(Some)
+    }
+
+    async fn write_partition<T: Record>(
+        &self,
+        partition: Partition,
+        records: &IndexSet<T>,
+    ) -> Result<(), PluckError> {
+        let filepath = self.partition_filepath::<T>(partition);
+
+        let contents = self.serialize(&filepath, records)?;
+
+        if let Some(parent_dir) = filepath.parent() {
+            tokio::fs::create_dir_all(parent_dir)
+                .await
+                .map_err(|err| PluckError::storage(parent_dir, err))?;
+        }
+
+        write_atomic(&filepath, contents.as_bytes(), self.keep_backups).await
+    }
+
+    /// Only the first record of the most recent partition is checked.
+    async fn contains<T: Record>(&self) -> Result<bool, PluckError> {
+        let Some(partition) = self.partitions::<T>().await?.pop() else {
+            return Ok(false);
+        };
+
+        let filepath = self.partition_filepath::<T>(partition);
+
+        let contents = tokio::fs::read_to_string(&filepath)
+            .await
+            .map_err(|err| PluckError::storage(&filepath, err))?;
+
+        let holds_records = match self.format {
+            FileFormat::Toml => {
+                let mut collections: BTreeMap<String, toml::Value> =
+                    toml::from_str(&contents).map_err(|err| PluckError::storage(&filepath, err))?;
+
+                match collections.remove(T::COLLECTION) {
+                    Some(toml::Value::Array(records)) => records
+                        .into_iter()
+                        .next()
+                        .is_some_and(|record| record.try_into::<T>().is_ok()),
+                    _ => false,
+                }
+            }
+            FileFormat::Json => {
+                let mut collections: BTreeMap<String, serde_json::Value> =
+                    serde_json::from_str(&contents)
+                        .map_err(|err| PluckError::storage(&filepath, err))?;
+
+                match collections.remove(T::COLLECTION) {
+                    Some(serde_json::Value::Array(records)) => records
+                        .into_iter()
+                        .next()
+                        .is_some_and(|record| serde_json::from_value::<T>(record).is_ok()),
+                    _ => false,
+                }
+            }
+            FileFormat::JsonLines => match contents.lines().find(|line| !line.trim().is_empty()) {
+                Some(line) => match serde_json::from_str::<T>(line) {
+                    Ok(_) => true,
+                    Err(err) if err.is_data() => false,
+                    Err(err) => return Err(PluckError::storage(&filepath, err)),
+                },
+                None => false,
+            },
+        };
+
+        if !holds_records {
+            return Ok(false);
+        }
+
+        // The rest of the records have to be readable too.
+        self.deserialize::<T>(&filepath, &contents)?;
+
+        Ok(true)
+    }
+
+    async fn read_state<S: DeserializeOwned>(
+        &self,
+        collection: &str,
+        key: &str,
+    ) -> Result<Option<S>, PluckError> {
+        read_json(&self.state_filepath(collection, key)).await
+    }
+
+    async fn write_state<S: Serialize + Sync>(
+        &self,
+        collection: &str,
+        key: &str,
+        state: &S,
+    ) -> Result<(), PluckError> {
+        write_json(&self.state_filepath(collection, key), state).await
+    }
+
+    async fn clear_state(&self, collection: &str, key: &str) -> Result<(), PluckError> {
+        let filepath = self.state_filepath(collection, key);
+
+        match tokio::fs::remove_file(&filepath).await {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(PluckError::storage(filepath, err)),
+        }
+    }
+}
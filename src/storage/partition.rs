@@ -0,0 +1,177 @@
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use serde::Deserialize;
+
+/// How records are split up within an archive.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Partitioning {
+    /// One partition per year, e.g. `2024.toml`.
+    #[default]
+    Year,
+
+    /// One partition per month, e.g. `2024/03.toml`.
+    Month,
+
+    /// One partition per day, e.g. `2024/03/15.toml`.
+    Day,
+
+    /// A single partition holding every record, e.g. `tracks.toml`.
+    Single,
+}
+
+impl Partitioning {
+    /// Returns the partition that a record with the given timestamp belongs to.
+    pub fn partition_of(&self, timestamp: DateTime<Utc>) -> Partition {
+        match self {
+            Self::Year => Partition::Year(timestamp.year()),
+            Self::Month => Partition::Month {
+                year: timestamp.year(),
+                month: timestamp.month(),
+            },
+            Self::Day => Partition::Day(timestamp.date_naive()),
+            Self::Single => Partition::All,
+        }
+    }
+}
+
+/// A group of records that are stored (and rewritten) together.
+///
+/// Partitions of the same [`Partitioning`] are ordered chronologically.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Partition {
+    All,
+    Year(i32),
+    Month { year: i32, month: u32 },
+    Day(NaiveDate),
+}
+
+impl fmt::Display for Partition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::All => write!(f, "all"),
+            Self::Year(year) => write!(f, "{}", year),
+            Self::Month { year, month } => write!(f, "{}-{:02}", year, month),
+            Self::Day(date) => write!(f, "{}", date.format("%Y-%m-%d")),
+        }
+    }
+}
+
+/// The error returned when parsing an invalid [`Partition`].
+#[derive(Debug)]
+pub struct InvalidPartition;
+
+impl FromStr for Partition {
+    type Err = InvalidPartition;
+
+    /// Parses a partition from the format it is displayed in, e.g. `2024-03`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if value == "all" {
+            return Ok(Self::All);
+        }
+
+        let parts = value
+            .split('-')
+            .map(|part| part.parse::<i32>().map_err(|_| InvalidPartition))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        match parts[..] {
+            [year] => Ok(Self::Year(year)),
+            [year, month] if (1..=12).contains(&month) => Ok(Self::Month {
+                year,
+                month: month as u32,
+            }),
+            [year, month, day] => NaiveDate::from_ymd_opt(year, month as u32, day as u32)
+                .map(Self::Day)
+                .ok_or(InvalidPartition),
+            _ => Err(InvalidPartition),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn partitions_parse_from_how_they_are_displayed() {
+        let partitions = [
+            Partition::All,
+            Partition::Year(2024),
+            Partition::Month {
+                year: 2024,
+                month: 3,
+            },
+            Partition::Day(NaiveDate::from_ymd_opt(2024, 3, 15).unwrap()),
+        ];
+
+        for partition in partitions {
+            assert_eq!(
+                partition.to_string().parse::<Partition>().unwrap(),
+                partition
+            );
+        }
+
+        assert_eq!(
+            "2024-03".parse::<Partition>().unwrap(),
+            Partition::Month {
+                year: 2024,
+                month: 3
+            }
+        );
+    }
+
+    #[test]
+    fn invalid_partitions_are_rejected() {
+        for value in [
+            "",
+            "tracks",
+            "2024-13",
+            "2024-00",
+            "2023-02-29",
+            "2024-03-15-01",
+        ] {
+            assert!(value.parse::<Partition>().is_err(), "{:?} parsed", value);
+        }
+    }
+
+    #[test]
+    fn records_are_partitioned_by_their_timestamp() {
+        let timestamp = Utc.with_ymd_and_hms(2024, 3, 15, 23, 59, 59).unwrap();
+
+        assert_eq!(
+            Partitioning::Year.partition_of(timestamp),
+            Partition::Year(2024)
+        );
+        assert_eq!(
+            Partitioning::Month.partition_of(timestamp),
+            Partition::Month {
+                year: 2024,
+                month: 3
+            }
+        );
+        assert_eq!(
+            Partitioning::Day.partition_of(timestamp),
+            Partition::Day(NaiveDate::from_ymd_opt(2024, 3, 15).unwrap())
+        );
+        assert_eq!(Partitioning::Single.partition_of(timestamp), Partition::All);
+    }
+
+    #[test]
+    fn partitions_are_ordered_chronologically() {
+        assert!(Partition::Year(2023) < Partition::Year(2024));
+        assert!(
+            Partition::Month {
+                year: 2023,
+                month: 12
+            } < Partition::Month {
+                year: 2024,
+                month: 1
+            }
+        );
+    }
+}
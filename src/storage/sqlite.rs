@@ -0,0 +1,319 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use indexmap::IndexSet;
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::models::Record;
+use crate::storage::{Partition, Partitioning, Storage};
+use crate::{PluckError, StorageError};
+
+/// Stores records in a SQLite database, with one table per collection.
+///
+/// Each record is stored as JSON alongside its ID, partition, and timestamp, so
+/// the database can be queried directly.
+#[derive(Clone)]
+pub struct SqliteStorage {
+    path: PathBuf,
+    partitioning: Partitioning,
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl SqliteStorage {
+    /// Opens (or creates) the SQLite database at the given path.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, PluckError> {
+        let path = path.into();
+
+        let connection = Connection::open(&path).map_err(|err| PluckError::storage(&path, err))?;
+
+        connection
+            .execute_batch(
+                "CREATE TABLE IF NOT EXISTS pluck_state (
+                    collection TEXT NOT NULL,
+                    key TEXT NOT NULL,
+                    value TEXT NOT NULL,
+                    PRIMARY KEY (collection, key)
+                );",
+            )
+            .map_err(|err| PluckError::storage(&path, err))?;
+
+        Ok(Self {
+            path,
+            partitioning: Partitioning::default(),
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    /// Opens an existing SQLite database without writing to it, such as the
+    /// source of a conversion.
+    pub fn open_read_only(path: impl Into<PathBuf>) -> Result<Self, PluckError> {
+        let path = path.into();
+
+        let connection = Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(|err| PluckError::storage(&path, err))?;
+
+        Ok(Self {
+            path,
+            partitioning: Partitioning::default(),
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    pub fn partition_by(&mut self, partitioning: Partitioning) -> &mut Self {
+        self.partitioning = partitioning;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Runs the given closure against the database on the blocking thread pool.
+    async fn with_connection<T, F>(&self, f: F) -> Result<T, PluckError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T, StorageError> + Send + 'static,
+    {
+        let connection = self.connection.clone();
+
+        let result = tokio::task::spawn_blocking(move || {
+            let mut connection = connection.lock().unwrap_or_else(|err| err.into_inner());
+
+            f(&mut connection)
+        })
+        .await
+        .map_err(|err| PluckError::storage(&self.path, std::io::Error::other(err)))?;
+
+        result.map_err(|err| PluckError::storage(&self.path, err))
+    }
+}
+
+/// Returns whether the database has a table with the given name.
+///
+/// Reads check this rather than creating missing tables, so that they work on
+/// databases opened read-only.
+fn table_exists(connection: &Connection, table: &str) -> rusqlite::Result<bool> {
+    connection.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1)",
+        [table],
+        |row| row.get(0),
+    )
+}
+
+/// Creates the table for the given collection, if it doesn't exist yet.
+///
+/// Records are keyed by their partition as well as their ID, like the file
+/// backends, so writing a partition never replaces a row in another one (e.g.
+/// a post that was reposted again months after being unreposted).
+fn ensure_collection_table(connection: &Connection, collection: &str) -> rusqlite::Result<()> {
+    connection.execute_batch(&format!(
+        "CREATE TABLE IF NOT EXISTS \"{collection}\" (
+            id TEXT NOT NULL,
+            partition_key TEXT NOT NULL,
+            timestamp TEXT NOT NULL,
+            data TEXT NOT NULL,
+            PRIMARY KEY (partition_key, id)
+        );"
+    ))
+}
+
+#[async_trait]
+impl Storage for SqliteStorage {
+    fn partitioning(&self) -> Partitioning {
+        self.partitioning
+    }
+
+    async fn partitions<T: Record>(&self) -> Result<Vec<Partition>, PluckError> {
+        self.with_connection(|connection| {
+            if !table_exists(connection, T::COLLECTION)? {
+                return Ok(Vec::new());
+            }
+
+            let mut statement = connection.prepare(&format!(
+                "SELECT DISTINCT partition_key FROM \"{}\"",
+                T::COLLECTION
+            ))?;
+
+            let mut partitions = statement
+                .query_map([], |row| row.get::<_, String>(0))?
+                .map(|partition_key| {
+                    let partition_key = partition_key?;
+
+                    partition_key
+                        .parse()
+                        .map_err(|_| StorageError::InvalidPartition(partition_key))
+                })
+                .collect::<Result<Vec<Partition>, StorageError>>()?;
+
+            partitions.sort_unstable();
+
+            Ok(partitions)
+        })
+        .await
+    }
+
+    async fn read_partition<T: Record>(
+        &self,
+        partition: Partition,
+    ) -> Result<Option<IndexSet<T>>, PluckError> {
+        self.with_connection(move |connection| {
+            if !table_exists(connection, T::COLLECTION)? {
+                return Ok(None);
+            }
+
+            let mut statement = connection.prepare(&format!(
+                "SELECT data FROM \"{}\" WHERE partition_key = ?1 ORDER BY rowid ASC",
+                T::COLLECTION
+            ))?;
+
+            let records = statement
+                .query_map([partition.to_string()], |row| row.get::<_, String>(0))?
+                .map(|data| Ok(serde_json::from_str(&data?)?))
+                .collect::<Result<IndexSet<T>, StorageError>>()?;
+
+            Ok((!records.is_empty()).then_some(records))
+        })
+        .await
+    }
+
+    async fn write_partition<T: Record>(
+        &self,
+        partition: Partition,
+        records: &IndexSet<T>,
+    ) -> Result<(), PluckError> {
+        let partition_key = partition.to_string();
+
+        let rows = records
+            .iter()
+            .map(|record| {
+                Ok((
+                    record.id(),
+                    record.timestamp().to_rfc3339(),
+                    serde_json::to_string(record)?,
+                ))
+            })
+            .collect::<Result<Vec<_>, StorageError>>()
+            .map_err(|err| PluckError::storage(&self.path, err))?;
+
+        self.with_connection(move |connection| {
+            ensure_collection_table(connection, T::COLLECTION)?;
+
+            let transaction = connection.transaction()?;
+
+            transaction.execute(
+                &format!("DELETE FROM \"{}\" WHERE partition_key = ?1", T::COLLECTION),
+                [&partition_key],
+            )?;
+
+            {
+                let mut statement = transaction.prepare(&format!(
+                    "INSERT OR REPLACE INTO \"{}\" (id, partition_key, timestamp, data) VALUES (?1, ?2, ?3, ?4)",
+                    T::COLLECTION
+                ))?;
+
+                for (id, timestamp, data) in rows {
+                    statement.execute(params![id, partition_key, timestamp, data])?;
+                }
+            }
+
+            transaction.commit()?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn contains<T: Record>(&self) -> Result<bool, PluckError> {
+        self.with_connection(|connection| {
+            if !table_exists(connection, T::COLLECTION)? {
+                return Ok(false);
+            }
+
+            let first_record: Option<String> = connection
+                .query_row(
+                    &format!(
+                        "SELECT data FROM \"{}\" ORDER BY rowid ASC LIMIT 1",
+                        T::COLLECTION
+                    ),
+                    [],
+                    |row| row.get(0),
+                )
+                .optional()?;
+
+            match first_record.map(|data| serde_json::from_str::<T>(&data)) {
+                Some(Ok(_)) => Ok(true),
+                Some(Err(err)) if err.is_data() => Ok(false),
+                Some(Err(err)) => Err(err.into()),
+                None => Ok(false),
+            }
+        })
+        .await
+    }
+
+    async fn read_state<S: DeserializeOwned>(
+        &self,
+        collection: &str,
+        key: &str,
+    ) -> Result<Option<S>, PluckError> {
+        let (collection, key) = (collection.to_owned(), key.to_owned());
+
+        let value: Option<String> = self
+            .with_connection(move |connection| {
+                if !table_exists(connection, "pluck_state")? {
+                    return Ok(None);
+                }
+
+                Ok(connection
+                    .query_row(
+                        "SELECT value FROM pluck_state WHERE collection = ?1 AND key = ?2",
+                        [&collection, &key],
+                        |row| row.get(0),
+                    )
+                    .optional()?)
+            })
+            .await?;
+
+        value
+            .map(|value| serde_json::from_str(&value))
+            .transpose()
+            .map_err(|err| PluckError::deserialize(self.path.display().to_string(), err))
+    }
+
+    async fn write_state<S: Serialize + Sync>(
+        &self,
+        collection: &str,
+        key: &str,
+        state: &S,
+    ) -> Result<(), PluckError> {
+        let value =
+            serde_json::to_string(state).map_err(|err| PluckError::storage(&self.path, err))?;
+        let (collection, key) = (collection.to_owned(), key.to_owned());
+
+        self.with_connection(move |connection| {
+            connection.execute(
+                "INSERT OR REPLACE INTO pluck_state (collection, key, value) VALUES (?1, ?2, ?3)",
+                [&collection, &key, &value],
+            )?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn clear_state(&self, collection: &str, key: &str) -> Result<(), PluckError> {
+        let (collection, key) = (collection.to_owned(), key.to_owned());
+
+        self.with_connection(move |connection| {
+            connection.execute(
+                "DELETE FROM pluck_state WHERE collection = ?1 AND key = ?2",
+                [&collection, &key],
+            )?;
+
+            Ok(())
+        })
+        .await
+    }
+}
@@ -0,0 +1,773 @@
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Duration, Utc};
+use indexmap::IndexSet;
+use serde::de::IgnoredAny;
+
+use crate::checkpoint::{HighWaterMark, SyncState};
+use crate::models::Record;
+use crate::source::{Page, Source};
+use crate::storage::{Partition, Storage};
+use crate::PluckError;
+
+/// Options that control how a [`sync`] behaves.
+#[derive(Debug, Clone)]
+pub struct SyncOptions {
+    /// Fetch every page, rather than stopping at the first item that has
+    /// already been archived.
+    pub full_sync: bool,
+
+    /// Continue an interrupted sync from its last checkpoint, if there is one.
+    pub resume: bool,
+
+    /// The number of pages to fetch between checkpoints.
+    pub checkpoint_interval: usize,
+
+    /// How far before the [`HighWaterMark`] an incremental sync re-checks for
+    /// items it may have missed, such as backdated posts.
+    pub overlap: Duration,
+
+    /// The delay between pages, in place of the source's own [`Source::pause`].
+    pub page_delay: Option<std::time::Duration>,
+}
+
+impl Default for SyncOptions {
+    fn default() -> Self {
+        Self {
+            full_sync: false,
+            resume: false,
+            checkpoint_interval: 10,
+            overlap: Duration::days(1),
+            page_delay: None,
+        }
+    }
+}
+
+/// The outcome of a completed [`sync`].
+#[derive(Debug, Clone, Default)]
+pub struct SyncSummary {
+    /// The number of pages fetched, including any fetched before resuming.
+    pub pages_fetched: usize,
+
+    /// The number of new items written to the archive, including any written before resuming.
+    pub items_written: usize,
+
+    /// The number of archived items that were marked as deleted upstream.
+    pub items_deleted: usize,
+}
+
+impl std::ops::AddAssign for SyncSummary {
+    fn add_assign(&mut self, other: Self) {
+        self.pages_fetched += other.pages_fetched;
+        self.items_written += other.items_written;
+        self.items_deleted += other.items_deleted;
+    }
+}
+
+/// Syncs the items from the given source into the given storage.
+///
+/// Incremental syncs fetch pages until they reach a page whose newest entry (see
+/// [`Page::newest_timestamp`]) is older than the [`HighWaterMark`] minus the
+/// [`SyncOptions::overlap`].
+/// Items are merged into whichever partition they belong to, so items
+/// straddling a partition boundary are handled correctly.
+///
+/// For sources that [track deletions](Source::tracks_deletions), archived items
+/// within the stretch of the source that was fetched (see [`FetchedItems`])
+/// that weren't fetched themselves are marked as deleted. Archived items are
+/// never dropped, not even by full syncs.
+///
+/// Progress is checkpointed every [`SyncOptions::checkpoint_interval`] pages by
+/// writing out the partitions along with a [`SyncState`], so that an interrupted
+/// sync can be resumed.
+pub async fn sync<S: Source>(
+    source: &mut S,
+    storage: &impl Storage,
+    options: &SyncOptions,
+) -> Result<SyncSummary, PluckError> {
+    let collection = S::Item::COLLECTION;
+
+    let checkpoint = if options.resume {
+        SyncState::<S::Cursor>::load(storage, collection).await?
+    } else {
+        None
+    };
+
+    let (full_sync, mut cursor, mut pages_fetched, mut items_written, mut newest_item) =
+        match checkpoint {
+            Some(state) => {
+                println!(
+                    "Resuming sync after {} pages ({} items written)",
+                    state.pages_fetched, state.items_written
+                );
+
+                (
+                    state.full_sync,
+                    Some(state.cursor),
+                    state.pages_fetched,
+                    state.items_written,
+                    state.newest_item,
+                )
+            }
+            None => (options.full_sync, None, 0, 0, None),
+        };
+
+    let high_water_mark = if !full_sync && source.supports_incremental_sync() {
+        match HighWaterMark::load(storage, collection).await? {
+            Some(high_water_mark) => Some(high_water_mark),
+            None => latest_archived_item::<S::Item>(storage).await?,
+        }
+    } else {
+        None
+    };
+
+    let cutoff = high_water_mark
+        .as_ref()
+        .map(|high_water_mark| high_water_mark.timestamp - options.overlap);
+
+    let partitioning = storage.partitioning();
+
+    let mut items_by_partition: HashMap<Partition, IndexSet<S::Item>> = HashMap::new();
+    let mut unflushed_partitions = HashSet::new();
+
+    let tracks_deletions = source.tracks_deletions();
+    let mut fetched_items = FetchedItems::default();
+
+    loop {
+        let Page {
+            items,
+            next_cursor,
+            newest_timestamp,
+        } = source.fetch_page(cursor).await?;
+        pages_fetched += 1;
+
+        // A page that the source filtered down to nothing says nothing about
+        // how far back the sync has got, so it never counts as past the cutoff.
+        let newest_timestamp =
+            newest_timestamp.or_else(|| items.iter().map(|item| item.timestamp()).max());
+
+        let reached_cutoff = match (cutoff, newest_timestamp) {
+            (Some(cutoff), Some(newest_timestamp)) => newest_timestamp < cutoff,
+            _ => false,
+        };
+
+        for item in items {
+            let partition = partitioning.partition_of(item.timestamp());
+
+            let item_mark = HighWaterMark {
+                id: item.id(),
+                timestamp: item.timestamp(),
+            };
+            newest_item = Some(item_mark.newest(newest_item));
+
+            if tracks_deletions {
+                fetched_items.insert(&item);
+            }
+
+            let partition_items = match items_by_partition.entry(partition) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    entry.insert(storage.read_partition(partition).await?.unwrap_or_default())
+                }
+            };
+
+            // Items that have already been archived are refreshed with the
+            // details of the fetched copy, rather than being replaced by it.
+            let (is_new_item, is_changed_item) = match partition_items.take(&item) {
+                Some(mut existing_item) => {
+                    let is_changed_item = existing_item.refresh(item);
+                    partition_items.insert(existing_item);
+
+                    (false, is_changed_item)
+                }
+                None => (partition_items.insert(item), false),
+            };
+
+            if is_new_item {
+                items_written += 1;
+            }
+
+            if is_new_item || is_changed_item {
+                unflushed_partitions.insert(partition);
+            }
+        }
+
+        if reached_cutoff {
+            break;
+        }
+
+        let Some(next_cursor) = next_cursor else {
+            break;
+        };
+
+        if options.checkpoint_interval > 0 && pages_fetched % options.checkpoint_interval == 0 {
+            flush(storage, &mut items_by_partition, &mut unflushed_partitions).await?;
+
+            SyncState {
+                full_sync,
+                cursor: &next_cursor,
+                pages_fetched,
+                items_written,
+                newest_item: newest_item.clone(),
+                updated_at: Utc::now(),
+            }
+            .save(storage, collection)
+            .await?;
+        }
+
+        cursor = Some(next_cursor);
+
+        match options.page_delay {
+            Some(page_delay) => tokio::time::sleep(page_delay).await,
+            None => source.pause(pages_fetched).await,
+        }
+    }
+
+    let items_deleted = if tracks_deletions {
+        mark_deleted_items(
+            storage,
+            &mut items_by_partition,
+            &mut unflushed_partitions,
+            &fetched_items,
+        )
+        .await?
+    } else {
+        0
+    };
+
+    if items_deleted > 0 {
+        println!("Marked {} {} as deleted", items_deleted, collection);
+    }
+
+    flush(storage, &mut items_by_partition, &mut unflushed_partitions).await?;
+
+    if let Some(newest_item) = newest_item {
+        newest_item
+            .newest(high_water_mark)
+            .save(storage, collection)
+            .await?;
+    }
+
+    SyncState::<S::Cursor>::clear(storage, collection).await?;
+
+    Ok(SyncSummary {
+        pages_fetched,
+        items_written,
+        items_deleted,
+    })
+}
+
+/// Returns whether syncing `T` records with the given options is a full sync,
+/// which is up to the checkpoint when an interrupted sync is resumed.
+pub async fn is_full_sync<T: Record>(
+    storage: &impl Storage,
+    options: &SyncOptions,
+) -> Result<bool, PluckError> {
+    if options.resume {
+        if let Some(state) = SyncState::<IgnoredAny>::load(storage, T::COLLECTION).await? {
+            return Ok(state.full_sync);
+        }
+    }
+
+    Ok(options.full_sync)
+}
+
+/// Returns the newest item in the most recent partition, for archives that
+/// predate high-water marks.
+async fn latest_archived_item<T: Record>(
+    storage: &impl Storage,
+) -> Result<Option<HighWaterMark>, PluckError> {
+    let Some((_, items)) = storage.latest_partition::<T>().await? else {
+        return Ok(None);
+    };
+
+    Ok(items
+        .iter()
+        .max_by_key(|item| item.timestamp())
+        .map(|item| HighWaterMark {
+            id: item.id(),
+            timestamp: item.timestamp(),
+        }))
+}
+
+/// A span of time, from the oldest to the newest.
+type Span = (DateTime<Utc>, DateTime<Utc>);
+
+/// The items fetched by a sync, which tell us which archived items would have
+/// been fetched again if they still existed upstream.
+///
+/// Sources may order their items by when they were indexed rather than by
+/// their timestamps, and a single backdated item stretches the span of one of
+/// the two back by years. So an archived item only counts as covered by the
+/// sync if it falls strictly within both spans.
+#[derive(Debug, Default)]
+struct FetchedItems {
+    ids: HashSet<String>,
+    timestamps: Option<Span>,
+
+    /// The span of the items' indexing times, if the source gives them.
+    indexing_times: Option<Span>,
+}
+
+impl FetchedItems {
+    fn insert<T: Record>(&mut self, item: &T) {
+        self.ids.insert(item.id());
+        self.timestamps = Some(extend(self.timestamps, item.timestamp()));
+
+        if let Some(indexed_at) = item.indexed_at() {
+            self.indexing_times = Some(extend(self.indexing_times, indexed_at));
+        }
+    }
+
+    /// Returns whether the given archived item would have been fetched by the
+    /// sync if it still existed upstream.
+    ///
+    /// Archived items that were never indexed (such as posts imported from a
+    /// repository) are assumed to have been indexed when they were created.
+    fn covers<T: Record>(&self, item: &T) -> bool {
+        let Some(timestamps) = self.timestamps else {
+            return false;
+        };
+
+        let timestamp = item.timestamp();
+
+        let indexed = match self.indexing_times {
+            Some(indexing_times) => {
+                strictly_within(indexing_times, item.indexed_at().unwrap_or(timestamp))
+            }
+            None => true,
+        };
+
+        indexed && strictly_within(timestamps, timestamp) && !self.ids.contains(&item.id())
+    }
+}
+
+fn extend(span: Option<Span>, time: DateTime<Utc>) -> Span {
+    match span {
+        Some((oldest, newest)) => (oldest.min(time), newest.max(time)),
+        None => (time, time),
+    }
+}
+
+fn strictly_within((oldest, newest): Span, time: DateTime<Utc>) -> bool {
+    oldest < time && time < newest
+}
+
+/// Marks the archived items that the sync covered (see [`FetchedItems::covers`])
+/// but didn't fetch as deleted.
+///
+/// Returns the number of items that were newly marked as deleted.
+async fn mark_deleted_items<T: Record>(
+    storage: &impl Storage,
+    items_by_partition: &mut HashMap<Partition, IndexSet<T>>,
+    unflushed_partitions: &mut HashSet<Partition>,
+    fetched_items: &FetchedItems,
+) -> Result<usize, PluckError> {
+    let Some((oldest, newest)) = fetched_items.timestamps else {
+        return Ok(0);
+    };
+
+    let partitioning = storage.partitioning();
+    let first_partition = partitioning.partition_of(oldest);
+    let last_partition = partitioning.partition_of(newest);
+
+    let deleted_at = Utc::now();
+    let mut items_deleted = 0;
+
+    // Partitions without any fetched items haven't been read yet, but may still
+    // hold items that have been deleted since.
+    for partition in storage.partitions::<T>().await? {
+        if partition < first_partition || partition > last_partition {
+            continue;
+        }
+
+        let partition_items = match items_by_partition.entry(partition) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                entry.insert(storage.read_partition(partition).await?.unwrap_or_default())
+            }
+        };
+
+        let mut partition_changed = false;
+
+        // Items in an `IndexSet` can't be changed in place, so the set is
+        // rebuilt, in the same order.
+        *partition_items = std::mem::take(partition_items)
+            .into_iter()
+            .map(|mut item| {
+                if fetched_items.covers(&item) && item.mark_deleted(deleted_at) {
+                    items_deleted += 1;
+                    partition_changed = true;
+                }
+
+                item
+            })
+            .collect();
+
+        if partition_changed {
+            unflushed_partitions.insert(partition);
+        }
+    }
+
+    Ok(items_deleted)
+}
+
+/// Writes out all of the partitions with items that haven't been written yet.
+async fn flush<T: Record>(
+    storage: &impl Storage,
+    items_by_partition: &mut HashMap<Partition, IndexSet<T>>,
+    unflushed_partitions: &mut HashSet<Partition>,
+) -> Result<(), PluckError> {
+    for partition in unflushed_partitions.drain() {
+        let Some(items) = items_by_partition.get_mut(&partition) else {
+            continue;
+        };
+
+        items.sort_unstable_by(|a, b| b.sort_key().cmp(&a.sort_key()));
+
+        storage.write_partition(partition, items).await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hash::{Hash, Hasher};
+
+    use async_trait::async_trait;
+    use chrono::TimeZone;
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::storage::SqliteStorage;
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Post {
+        id: u32,
+        created_at: DateTime<Utc>,
+        indexed_at: Option<DateTime<Utc>>,
+        text: String,
+        deleted_at: Option<DateTime<Utc>>,
+    }
+
+    impl PartialEq for Post {
+        fn eq(&self, other: &Self) -> bool {
+            self.id == other.id
+        }
+    }
+
+    impl Eq for Post {}
+
+    impl Hash for Post {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            self.id.hash(state);
+        }
+    }
+
+    impl Record for Post {
+        const COLLECTION: &'static str = "posts";
+
+        type SortKey = DateTime<Utc>;
+
+        fn id(&self) -> String {
+            self.id.to_string()
+        }
+
+        fn timestamp(&self) -> DateTime<Utc> {
+            self.created_at
+        }
+
+        fn sort_key(&self) -> DateTime<Utc> {
+            self.created_at
+        }
+
+        fn indexed_at(&self) -> Option<DateTime<Utc>> {
+            self.indexed_at
+        }
+
+        fn refresh(&mut self, fetched: Self) -> bool {
+            let changed = self.text != fetched.text;
+            self.text = fetched.text;
+
+            changed
+        }
+
+        fn mark_deleted(&mut self, deleted_at: DateTime<Utc>) -> bool {
+            if self.deleted_at.is_some() {
+                return false;
+            }
+
+            self.deleted_at = Some(deleted_at);
+
+            true
+        }
+    }
+
+    /// A page of posts, listed with when they were indexed.
+    struct FakePage {
+        posts: Vec<(u32, DateTime<Utc>)>,
+
+        /// The timestamp of the page's newest entry before filtering, if it
+        /// differs from its posts'.
+        newest_timestamp: Option<DateTime<Utc>>,
+    }
+
+    struct FakeSource {
+        pages: Vec<FakePage>,
+        text: &'static str,
+        tracks_deletions: bool,
+
+        /// When the given posts were created, for posts that were backdated.
+        backdated: HashMap<u32, DateTime<Utc>>,
+    }
+
+    impl FakeSource {
+        fn new(pages: Vec<Vec<(u32, DateTime<Utc>)>>) -> Self {
+            Self {
+                pages: pages
+                    .into_iter()
+                    .map(|posts| FakePage {
+                        posts,
+                        newest_timestamp: None,
+                    })
+                    .collect(),
+                text: "original",
+                tracks_deletions: false,
+                backdated: HashMap::new(),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl Source for FakeSource {
+        type Item = Post;
+        type Cursor = usize;
+
+        fn tracks_deletions(&self) -> bool {
+            self.tracks_deletions
+        }
+
+        async fn fetch_page(
+            &mut self,
+            cursor: Option<usize>,
+        ) -> Result<Page<Post, usize>, PluckError> {
+            let index = cursor.unwrap_or_default();
+            let page = &self.pages[index];
+
+            Ok(Page {
+                items: page
+                    .posts
+                    .iter()
+                    .map(|&(id, indexed_at)| Post {
+                        id,
+                        created_at: self.backdated.get(&id).copied().unwrap_or(indexed_at),
+                        indexed_at: Some(indexed_at),
+                        text: self.text.to_string(),
+                        deleted_at: None,
+                    })
+                    .collect(),
+                next_cursor: (index + 1 < self.pages.len()).then_some(index + 1),
+                newest_timestamp: page.newest_timestamp,
+            })
+        }
+
+        async fn pause(&self, _pages_fetched: usize) {}
+    }
+
+    fn day(day: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, day, 12, 0, 0).unwrap()
+    }
+
+    async fn archived_posts(storage: &SqliteStorage) -> Vec<Post> {
+        let mut posts = Vec::new();
+
+        for partition in storage.partitions::<Post>().await.unwrap() {
+            posts.extend(
+                storage
+                    .read_partition::<Post>(partition)
+                    .await
+                    .unwrap()
+                    .unwrap(),
+            );
+        }
+
+        posts
+    }
+
+    async fn storage_with(ids_and_days: Vec<(u32, DateTime<Utc>)>) -> SqliteStorage {
+        let storage = SqliteStorage::open(":memory:").unwrap();
+
+        sync(
+            &mut FakeSource::new(vec![ids_and_days]),
+            &storage,
+            &SyncOptions::default(),
+        )
+        .await
+        .unwrap();
+
+        storage
+    }
+
+    #[tokio::test]
+    async fn archived_items_are_refreshed_rather_than_duplicated() {
+        let storage = storage_with(vec![(2, day(2)), (1, day(1))]).await;
+
+        let mut source = FakeSource::new(vec![vec![(2, day(2)), (1, day(1))]]);
+        source.text = "edited";
+
+        let summary = sync(&mut source, &storage, &SyncOptions::default())
+            .await
+            .unwrap();
+
+        assert_eq!(summary.items_written, 0);
+
+        let posts = archived_posts(&storage).await;
+        assert_eq!(posts.len(), 2);
+        assert!(posts.iter().all(|post| post.text == "edited"));
+    }
+
+    #[tokio::test]
+    async fn resumed_syncs_keep_the_checkpointed_kind_of_sync() {
+        let storage = SqliteStorage::open(":memory:").unwrap();
+
+        SyncState {
+            full_sync: true,
+            cursor: 3usize,
+            pages_fetched: 3,
+            items_written: 0,
+            newest_item: None,
+            updated_at: Utc::now(),
+        }
+        .save(&storage, Post::COLLECTION)
+        .await
+        .unwrap();
+
+        let options = SyncOptions {
+            resume: true,
+            ..SyncOptions::default()
+        };
+
+        assert!(is_full_sync::<Post>(&storage, &options).await.unwrap());
+        assert!(!is_full_sync::<Post>(&storage, &SyncOptions::default())
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn incremental_sync_stops_past_the_cutoff() {
+        let storage = storage_with(vec![(10, day(10))]).await;
+
+        let mut source = FakeSource::new(vec![
+            vec![(20, day(20))],
+            vec![(5, day(5))],
+            vec![(1, day(1))],
+        ]);
+
+        let summary = sync(&mut source, &storage, &SyncOptions::default())
+            .await
+            .unwrap();
+
+        assert_eq!(summary.pages_fetched, 2);
+        assert_eq!(summary.items_written, 2);
+        assert_eq!(archived_posts(&storage).await.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn filtered_pages_are_judged_by_their_newest_entry() {
+        let storage = storage_with(vec![(10, day(10))]).await;
+
+        let mut source = FakeSource::new(vec![vec![], vec![(15, day(15))], vec![(5, day(5))]]);
+        source.pages[0].newest_timestamp = Some(day(16));
+
+        let summary = sync(&mut source, &storage, &SyncOptions::default())
+            .await
+            .unwrap();
+
+        assert_eq!(summary.pages_fetched, 3);
+        assert_eq!(summary.items_written, 2);
+
+        let mut source = FakeSource::new(vec![vec![], vec![(4, day(4))]]);
+        source.pages[0].newest_timestamp = Some(day(5));
+
+        let summary = sync(&mut source, &storage, &SyncOptions::default())
+            .await
+            .unwrap();
+
+        assert_eq!(summary.pages_fetched, 1);
+    }
+
+    #[tokio::test]
+    async fn items_missing_from_the_fetched_span_are_marked_deleted() {
+        let storage = storage_with(vec![(4, day(4)), (3, day(3)), (2, day(2)), (1, day(1))]).await;
+
+        let mut source = FakeSource::new(vec![vec![(4, day(4)), (2, day(2))]]);
+        source.tracks_deletions = true;
+
+        let summary = sync(&mut source, &storage, &SyncOptions::default())
+            .await
+            .unwrap();
+
+        assert_eq!(summary.items_deleted, 1);
+
+        let deleted = archived_posts(&storage)
+            .await
+            .into_iter()
+            .filter(|post| post.deleted_at.is_some())
+            .map(|post| post.id)
+            .collect::<Vec<_>>();
+
+        // The oldest post is outside the span that was fetched.
+        assert_eq!(deleted, vec![3]);
+    }
+
+    #[tokio::test]
+    async fn backdated_items_dont_stretch_the_deletion_window() {
+        let storage = storage_with(vec![(4, day(4)), (3, day(3)), (2, day(2)), (1, day(1))]).await;
+
+        // A post indexed alongside the others, but backdated by a year.
+        let mut source = FakeSource::new(vec![vec![(4, day(4)), (9, day(4)), (3, day(3))]]);
+        source.tracks_deletions = true;
+        source.backdated.insert(9, day(1) - Duration::days(365));
+
+        let summary = sync(&mut source, &storage, &SyncOptions::default())
+            .await
+            .unwrap();
+
+        assert_eq!(summary.items_deleted, 0);
+    }
+
+    #[tokio::test]
+    async fn full_syncs_refresh_archived_items_without_dropping_any() {
+        let storage = storage_with(vec![(3, day(3)), (2, day(2)), (1, day(1))]).await;
+
+        let mut source = FakeSource::new(vec![vec![(3, day(3)), (1, day(1))]]);
+        source.tracks_deletions = true;
+
+        sync(&mut source, &storage, &SyncOptions::default())
+            .await
+            .unwrap();
+
+        let mut source = FakeSource::new(vec![vec![(3, day(3))], vec![(1, day(1))]]);
+        source.text = "edited";
+
+        let options = SyncOptions {
+            full_sync: true,
+            ..SyncOptions::default()
+        };
+
+        let summary = sync(&mut source, &storage, &options).await.unwrap();
+
+        assert_eq!(summary.pages_fetched, 2);
+        assert_eq!(summary.items_written, 0);
+
+        let posts = archived_posts(&storage).await;
+        assert_eq!(posts.len(), 3);
+
+        for post in posts {
+            assert_eq!(post.deleted_at.is_some(), post.id == 2);
+            assert_eq!(post.text == "edited", post.id != 2);
+        }
+    }
+}
@@ -1,22 +1,36 @@
-use std::fs::File;
-use std::io::Read;
-use std::path::{Path, PathBuf};
+mod archive;
+mod types;
 
-use chrono::{DateTime, TimeZone, Utc};
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Deserializer};
 use serde_with::{serde_as, DisplayFromStr};
 
+use crate::media::{extension_of_url, MediaStore};
+use crate::models::{DirectMessage, Follower, Following, Like, Record, Tweet, TwitterProfile};
+use crate::retry::RetryPolicy;
+use crate::source::{Page, Source};
+use crate::PluckError;
+
+pub use archive::*;
+pub use types::*;
+
 /// The date format used by tweets stored in a Twitter archive.
 ///
 /// Matches the following format: `Fri Sep 28 22:03:55 +0000 2018`.
-const DATE_FORMAT: &'static str = "%a %b %d %H:%M:%S %z %Y";
+const DATE_FORMAT: &str = "%a %b %d %H:%M:%S %z %Y";
 
 pub fn deserialize_date<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
 where
     D: Deserializer<'de>,
 {
     let s = String::deserialize(deserializer)?;
-    Utc.datetime_from_str(&s, DATE_FORMAT)
+    DateTime::parse_from_str(&s, DATE_FORMAT)
+        .map(|date| date.with_timezone(&Utc))
         .map_err(serde::de::Error::custom)
 }
 
@@ -106,16 +120,120 @@ pub struct ArchivedTweetMediaEntity {
     pub media_url_https: String,
 }
 
+/// The directory in a Twitter archive's `data` directory that holds the
+/// media attached to tweets.
+const TWEETS_MEDIA_DIR_NAME: &str = "tweets_media";
+
+pub struct TwitterTimelineFetcher {
+    timeline: egg_mode::tweet::Timeline,
+    retry_policy: RetryPolicy,
+    media_store: Option<MediaStore>,
+}
+
+impl TwitterTimelineFetcher {
+    pub fn new(screen_name: String, token: &egg_mode::Token) -> Self {
+        Self {
+            timeline: egg_mode::tweet::user_timeline(screen_name, true, false, token)
+                .with_page_size(200),
+            retry_policy: RetryPolicy::default(),
+            media_store: None,
+        }
+    }
+
+    pub fn with_retry_policy(&mut self, retry_policy: RetryPolicy) -> &mut Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Downloads the media attached to each tweet into the given store.
+    pub fn with_media_store(&mut self, media_store: MediaStore) -> &mut Self {
+        self.media_store = Some(media_store);
+        self
+    }
+}
+
+/// Returns the URL to download the given media from: the highest bitrate MP4
+/// for videos and GIFs, or the image itself for photos.
+fn media_download_url(entity: &egg_mode::entities::MediaEntity) -> String {
+    entity
+        .video_info
+        .as_ref()
+        .and_then(|video_info| {
+            video_info
+                .variants
+                .iter()
+                .filter(|variant| variant.content_type.essence_str() == "video/mp4")
+                .max_by_key(|variant| variant.bitrate.unwrap_or_default())
+        })
+        .map(|variant| variant.url.clone())
+        .unwrap_or_else(|| entity.media_url_https.clone())
+}
+
+#[async_trait]
+impl Source for TwitterTimelineFetcher {
+    type Item = Tweet;
+
+    /// The ID of the newest tweet to fetch.
+    type Cursor = u64;
+
+    async fn fetch_page(&mut self, max_id: Option<u64>) -> Result<Page<Tweet, u64>, PluckError> {
+        let feed = self
+            .retry_policy
+            .retry(|| async { Ok(self.timeline.call(None, max_id).await?) })
+            .await?;
+
+        let next_max_id = feed.response.last().map(|tweet| tweet.id - 1);
+
+        let mut tweets = Vec::with_capacity(feed.response.len());
+
+        for tweet in feed.response {
+            let download_urls = tweet
+                .entities
+                .media
+                .iter()
+                .flatten()
+                .map(media_download_url)
+                .collect::<Vec<_>>();
+
+            let mut tweet = Tweet::from(tweet);
+
+            if let Some(media_store) = &self.media_store {
+                let media = tweet
+                    .entities
+                    .iter_mut()
+                    .flat_map(|entities| entities.media.iter_mut().flatten());
+
+                for (entity, url) in media.zip(download_urls) {
+                    match media_store.download(&url, None).await {
+                        Ok(stored_media) => entity.set_stored_media(stored_media),
+                        Err(err) => eprintln!("Failed to download {}: {}", url, err),
+                    }
+                }
+            }
+
+            tweets.push(tweet);
+        }
+
+        Ok(Page {
+            items: tweets,
+            next_cursor: next_max_id,
+            newest_timestamp: None,
+        })
+    }
+}
+
 pub struct TwitterArchiveImporter {
-    tweet_files: Vec<PathBuf>,
+    archive: Arc<TwitterArchive>,
     include_retweets: bool,
+    media_store: Option<MediaStore>,
 }
 
 impl TwitterArchiveImporter {
-    pub fn new<P: Into<PathBuf>>(tweet_files: Vec<P>) -> Self {
+    pub fn new(archive: Arc<TwitterArchive>) -> Self {
         Self {
-            tweet_files: tweet_files.into_iter().map(Into::into).collect(),
+            archive,
             include_retweets: false,
+            media_store: None,
         }
     }
 
@@ -124,10 +242,25 @@ impl TwitterArchiveImporter {
         self
     }
 
-    pub fn get_tweets(&self) -> Result<Vec<ArchivedTweet>, Box<dyn std::error::Error>> {
+    /// Copies the media attached to each tweet out of the archive into the given store.
+    pub fn with_media_store(&mut self, media_store: MediaStore) -> &mut Self {
+        self.media_store = Some(media_store);
+        self
+    }
+
+    pub fn get_tweets(&self) -> Result<Vec<ArchivedTweet>, PluckError> {
+        let tweet_files = self.archive.data_files("tweets")?;
+
+        if tweet_files.is_empty() {
+            return Err(PluckError::storage(
+                self.archive.path(),
+                std::io::Error::new(std::io::ErrorKind::NotFound, "no tweets.js in archive"),
+            ));
+        }
+
         let mut all_tweets = Vec::new();
 
-        for tweet_file in &self.tweet_files {
+        for tweet_file in &tweet_files {
             let tweets = self.get_tweets_from_file(tweet_file)?;
 
             all_tweets.extend(tweets);
@@ -136,23 +269,20 @@ impl TwitterArchiveImporter {
         Ok(all_tweets)
     }
 
-    fn get_tweets_from_file(
-        &self,
-        tweet_filepath: &Path,
-    ) -> Result<Vec<ArchivedTweet>, Box<dyn std::error::Error>> {
-        let mut tweet_file = File::open(&tweet_filepath)?;
-
-        let mut buffer = String::new();
-        tweet_file.read_to_string(&mut buffer)?;
-
-        let buffer = buffer
-            .replace("window.YTD.tweets.part0 = ", "")
-            .replace("window.YTD.tweets.part1 = ", "")
+    fn get_tweets_from_file(&self, tweet_file: &str) -> Result<Vec<ArchivedTweet>, PluckError> {
+        let buffer = self
+            .archive
+            .read_data_file(tweet_file)?
             .replace("&amp;", "&")
             .replace("&lt;", "<")
             .replace("&gt;", ">");
 
-        let raw_tweets: Vec<serde_json::Value> = serde_json::from_str(&buffer)?;
+        let raw_tweets: Vec<serde_json::Value> = serde_json::from_str(&buffer).map_err(|err| {
+            PluckError::deserialize(
+                self.archive.path().join(tweet_file).display().to_string(),
+                err,
+            )
+        })?;
 
         let mut tweets: Vec<ArchivedTweet> = Vec::new();
         for raw_tweet in raw_tweets {
@@ -166,14 +296,341 @@ impl TwitterArchiveImporter {
                         tweets.push(tweet);
                     }
                 }
-                Err(err) => eprintln!(
-                    "Failed to parse tweet: {}\n\n{}",
-                    err,
-                    serde_json::to_string_pretty(&raw_tweet)?
-                ),
+                Err(err) => eprintln!("Failed to parse tweet: {}\n\n{:#}", err, raw_tweet),
             }
         }
 
         Ok(tweets)
     }
 }
+
+impl TwitterArchiveImporter {
+    /// Copies the media attached to the given tweet out of the archive.
+    ///
+    /// Media files are named after the tweet they are attached to, e.g.
+    /// `1045813961358376960-DoS7lz0WwAA3bVD.jpg`. Videos and GIFs are archived as
+    /// MP4s, whereas their entities point at a thumbnail (under `ext_tw_video_thumb`
+    /// or `tweet_video_thumb`), so those are matched by the tweet ID alone.
+    async fn copy_tweet_media(
+        &self,
+        media_store: &MediaStore,
+        media_file_names: &[String],
+        tweet: &mut Tweet,
+    ) -> Result<(), PluckError> {
+        let Some(media) = tweet
+            .entities
+            .as_mut()
+            .and_then(|entities| entities.media.as_mut())
+        else {
+            return Ok(());
+        };
+
+        let prefix = format!("{}-", tweet.id);
+
+        for entity in media {
+            let url_file_name = entity.url.rsplit('/').next().unwrap_or_default();
+
+            // Archives mark videos and GIFs as photos, but their thumbnails give them away.
+            let is_video_or_gif = entity.r#type != crate::models::MediaType::Photo
+                || entity.url.contains("video_thumb");
+
+            let media_file_name = media_file_names
+                .iter()
+                .find(|file_name| file_name.strip_prefix(&prefix) == Some(url_file_name))
+                .or_else(|| {
+                    if !is_video_or_gif {
+                        return None;
+                    }
+
+                    media_file_names.iter().find(|file_name| {
+                        file_name.starts_with(&prefix) && file_name.ends_with(".mp4")
+                    })
+                });
+
+            let Some(media_file_name) = media_file_name else {
+                eprintln!("No media for {} in archive", entity.url);
+                continue;
+            };
+
+            let contents = self
+                .archive
+                .read(&format!("{}/{}", TWEETS_MEDIA_DIR_NAME, media_file_name))?;
+
+            let stored_media = media_store
+                .save(&contents, extension_of_url(media_file_name))
+                .await?;
+
+            entity.set_stored_media(stored_media);
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Source for TwitterArchiveImporter {
+    type Item = Tweet;
+    type Cursor = ();
+
+    fn supports_incremental_sync(&self) -> bool {
+        false
+    }
+
+    async fn fetch_page(&mut self, _cursor: Option<()>) -> Result<Page<Tweet, ()>, PluckError> {
+        let mut tweets = self
+            .get_tweets()?
+            .into_iter()
+            .map(Tweet::from)
+            .collect::<Vec<_>>();
+
+        if let Some(media_store) = &self.media_store {
+            let media_file_names = self.archive.file_names(TWEETS_MEDIA_DIR_NAME)?;
+
+            for tweet in &mut tweets {
+                self.copy_tweet_media(media_store, &media_file_names, tweet)
+                    .await?;
+            }
+        }
+
+        Ok(Page {
+            items: tweets,
+            next_cursor: None,
+            newest_timestamp: None,
+        })
+    }
+}
+
+/// A kind of data, besides tweets, that can be imported from a Twitter archive.
+pub trait ArchivedData: DeserializeOwned {
+    /// The names of the data files holding this kind of data, e.g. `like` for `like.js`.
+    const FILE_NAMES: &'static [&'static str];
+
+    type Record: Record;
+
+    fn into_records(self) -> Vec<Self::Record>;
+}
+
+impl ArchivedData for ArchivedLikeWrapper {
+    const FILE_NAMES: &'static [&'static str] = &["like"];
+
+    type Record = Like;
+
+    fn into_records(self) -> Vec<Like> {
+        vec![Like::from(self.like)]
+    }
+}
+
+impl ArchivedData for ArchivedDmConversationWrapper {
+    const FILE_NAMES: &'static [&'static str] = &["direct-messages", "direct-messages-group"];
+
+    type Record = DirectMessage;
+
+    fn into_records(self) -> Vec<DirectMessage> {
+        let conversation = self.dm_conversation;
+        let conversation_id = conversation.conversation_id;
+
+        conversation
+            .messages
+            .into_iter()
+            .filter_map(|event| event.message_create)
+            .map(|message| DirectMessage::from_archived(conversation_id.clone(), message))
+            .collect()
+    }
+}
+
+impl ArchivedData for ArchivedFollowerWrapper {
+    const FILE_NAMES: &'static [&'static str] = &["follower"];
+
+    type Record = Follower;
+
+    fn into_records(self) -> Vec<Follower> {
+        vec![Follower::from(self.follower)]
+    }
+}
+
+impl ArchivedData for ArchivedFollowingWrapper {
+    const FILE_NAMES: &'static [&'static str] = &["following"];
+
+    type Record = Following;
+
+    fn into_records(self) -> Vec<Following> {
+        vec![Following::from(self.following)]
+    }
+}
+
+/// Reads every entry of the given kind of data from an archive.
+///
+/// Entries that fail to parse are logged and skipped, and kinds of data that
+/// aren't in the archive at all come back empty.
+fn read_archived_entries<T: DeserializeOwned>(
+    archive: &TwitterArchive,
+    names: &[&str],
+) -> Result<Vec<T>, PluckError> {
+    let mut entries = Vec::new();
+
+    for name in names {
+        for data_file in archive.data_files(name)? {
+            let buffer = archive.read_data_file(&data_file)?;
+
+            let raw_entries: Vec<serde_json::Value> =
+                serde_json::from_str(&buffer).map_err(|err| {
+                    PluckError::deserialize(
+                        archive.path().join(&data_file).display().to_string(),
+                        err,
+                    )
+                })?;
+
+            for raw_entry in raw_entries {
+                match serde_json::from_value::<T>(raw_entry.clone()) {
+                    Ok(entry) => entries.push(entry),
+                    Err(err) => eprintln!(
+                        "Failed to parse entry in {}: {}\n\n{:#}",
+                        data_file, err, raw_entry
+                    ),
+                }
+            }
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Imports one kind of [`ArchivedData`] from a Twitter archive.
+pub struct TwitterArchiveDataImporter<T> {
+    archive: Arc<TwitterArchive>,
+    data: PhantomData<fn() -> T>,
+}
+
+impl<T: ArchivedData> TwitterArchiveDataImporter<T> {
+    pub fn new(archive: Arc<TwitterArchive>) -> Self {
+        Self {
+            archive,
+            data: PhantomData,
+        }
+    }
+}
+
+#[async_trait]
+impl<T: ArchivedData> Source for TwitterArchiveDataImporter<T> {
+    type Item = T::Record;
+    type Cursor = ();
+
+    fn supports_incremental_sync(&self) -> bool {
+        false
+    }
+
+    async fn fetch_page(&mut self, _cursor: Option<()>) -> Result<Page<T::Record, ()>, PluckError> {
+        let entries = read_archived_entries::<T>(&self.archive, T::FILE_NAMES)?;
+
+        Ok(Page {
+            items: entries.into_iter().flat_map(T::into_records).collect(),
+            next_cursor: None,
+            newest_timestamp: None,
+        })
+    }
+}
+
+/// Imports the account and profile of the owner of a Twitter archive.
+pub struct TwitterProfileImporter {
+    archive: Arc<TwitterArchive>,
+}
+
+impl TwitterProfileImporter {
+    pub fn new(archive: Arc<TwitterArchive>) -> Self {
+        Self { archive }
+    }
+}
+
+#[async_trait]
+impl Source for TwitterProfileImporter {
+    type Item = TwitterProfile;
+    type Cursor = ();
+
+    fn supports_incremental_sync(&self) -> bool {
+        false
+    }
+
+    async fn fetch_page(
+        &mut self,
+        _cursor: Option<()>,
+    ) -> Result<Page<TwitterProfile, ()>, PluckError> {
+        let accounts =
+            read_archived_entries::<ArchivedAccountWrapper>(&self.archive, &["account"])?;
+        let profile = read_archived_entries::<ArchivedProfileWrapper>(&self.archive, &["profile"])?
+            .into_iter()
+            .next()
+            .map(|wrapper| wrapper.profile);
+
+        Ok(Page {
+            items: accounts
+                .into_iter()
+                .next()
+                .map(|wrapper| TwitterProfile::from_archived(wrapper.account, profile))
+                .into_iter()
+                .collect(),
+            next_cursor: None,
+            newest_timestamp: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// Returns an empty directory for a test to write to.
+    fn empty_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("pluck-{}-{}", name, std::process::id()));
+
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    #[tokio::test]
+    async fn archived_data_is_read_from_every_part() {
+        let dir = empty_dir("twitter-archive-likes");
+
+        std::fs::write(
+            dir.join("like.js"),
+            r#"window.YTD.like.part0 = [
+                { "like": { "tweetId": "1", "fullText": "first", "expandedUrl": "https://twitter.com/i/web/status/1" } },
+                { "like": { "tweetId": "not a number" } }
+            ]"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("like-part1.js"),
+            r#"window.YTD.like.part1 = [{ "like": { "tweetId": "2" } }]"#,
+        )
+        .unwrap();
+
+        let archive = Arc::new(TwitterArchive::open(&dir).unwrap());
+
+        let page = TwitterArchiveDataImporter::<ArchivedLikeWrapper>::new(archive.clone())
+            .fetch_page(None)
+            .await
+            .unwrap();
+
+        let tweet_ids = page
+            .items
+            .iter()
+            .map(|like| like.tweet_id)
+            .collect::<Vec<_>>();
+        assert_eq!(tweet_ids, vec![1, 2]);
+        assert_eq!(page.items[0].text.as_deref(), Some("first"));
+
+        // Kinds of data that aren't in the archive come back empty.
+        let page = TwitterArchiveDataImporter::<ArchivedFollowerWrapper>::new(archive)
+            .fetch_page(None)
+            .await
+            .unwrap();
+
+        assert!(page.items.is_empty());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,308 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use zip::ZipArchive;
+
+use crate::PluckError;
+
+/// The name of the directory in a Twitter archive that holds the data files.
+const DATA_DIR_NAME: &str = "data";
+
+/// A Twitter archive, either as downloaded (a `.zip`) or extracted into a directory.
+pub enum TwitterArchive {
+    /// An extracted archive, with the path to its `data` directory.
+    Directory(PathBuf),
+
+    /// A downloaded archive, read without extracting it.
+    Zip {
+        path: PathBuf,
+        zip: Mutex<ZipArchive<File>>,
+
+        /// The path of the `data` directory within the zip, e.g. `data/`.
+        data_prefix: String,
+    },
+}
+
+impl TwitterArchive {
+    /// Opens the Twitter archive at the given path, which can be the downloaded
+    /// `.zip`, the directory it was extracted to, or the `data` directory within it.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, PluckError> {
+        let path = path.into();
+
+        if path.is_dir() {
+            let data_dir = path.join(DATA_DIR_NAME);
+
+            return Ok(Self::Directory(if data_dir.is_dir() {
+                data_dir
+            } else {
+                path
+            }));
+        }
+
+        let file = File::open(&path).map_err(|err| PluckError::storage(&path, err))?;
+        let zip = ZipArchive::new(file).map_err(|err| PluckError::storage(&path, err))?;
+
+        // Archives are sometimes re-zipped with an extra top-level directory, so
+        // look for the `data` directory wherever it is.
+        let data_prefix = zip
+            .file_names()
+            .find_map(|name| {
+                let data_dir = format!("{}/", DATA_DIR_NAME);
+
+                name.match_indices(&data_dir)
+                    .map(|(index, _)| index)
+                    .find(|&index| index == 0 || name[..index].ends_with('/'))
+                    .map(|index| name[..index + data_dir.len()].to_string())
+            })
+            .unwrap_or_default();
+
+        Ok(Self::Zip {
+            path,
+            zip: Mutex::new(zip),
+            data_prefix,
+        })
+    }
+
+    /// Returns the path to the archive on disk.
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::Directory(data_dir) => data_dir,
+            Self::Zip { path, .. } => path,
+        }
+    }
+
+    /// Returns the names of the files in the given directory, relative to the
+    /// `data` directory, or an empty list if there is no such directory.
+    pub fn file_names(&self, dir: &str) -> Result<Vec<String>, PluckError> {
+        match self {
+            Self::Directory(data_dir) => {
+                let dir = data_dir.join(dir);
+
+                let entries = match std::fs::read_dir(&dir) {
+                    Ok(entries) => entries,
+                    Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+                    Err(err) => return Err(PluckError::storage(dir, err)),
+                };
+
+                let mut file_names = Vec::new();
+
+                for entry in entries {
+                    let entry = entry.map_err(|err| PluckError::storage(&dir, err))?;
+
+                    if entry.path().is_file() {
+                        if let Some(file_name) = entry.file_name().to_str() {
+                            file_names.push(file_name.to_string());
+                        }
+                    }
+                }
+
+                Ok(file_names)
+            }
+            Self::Zip {
+                zip, data_prefix, ..
+            } => {
+                let prefix = if dir.is_empty() {
+                    data_prefix.clone()
+                } else {
+                    format!("{}{}/", data_prefix, dir)
+                };
+
+                let zip = zip.lock().unwrap_or_else(|err| err.into_inner());
+
+                Ok(zip
+                    .file_names()
+                    .filter_map(|name| name.strip_prefix(prefix.as_str()))
+                    .filter(|file_name| !file_name.is_empty() && !file_name.contains('/'))
+                    .map(ToString::to_string)
+                    .collect())
+            }
+        }
+    }
+
+    /// Returns the data files for the given kind of data, in order.
+    ///
+    /// Large archives split data across several files, e.g. `tweets.js`,
+    /// `tweets-part1.js`, `tweets-part2.js`, and so on.
+    pub fn data_files(&self, name: &str) -> Result<Vec<String>, PluckError> {
+        let mut parts = self
+            .file_names("")?
+            .into_iter()
+            .filter_map(|file_name| {
+                let stem = file_name.strip_suffix(".js")?;
+
+                let part = if stem == name {
+                    0
+                } else {
+                    stem.strip_prefix(name)?
+                        .strip_prefix("-part")?
+                        .parse::<u32>()
+                        .ok()?
+                };
+
+                Some((part, file_name))
+            })
+            .collect::<Vec<_>>();
+
+        parts.sort_unstable();
+
+        Ok(parts.into_iter().map(|(_, file_name)| file_name).collect())
+    }
+
+    /// Reads the file at the given path, relative to the `data` directory.
+    pub fn read(&self, relative_path: &str) -> Result<Vec<u8>, PluckError> {
+        let mut buffer = Vec::new();
+
+        match self {
+            Self::Directory(data_dir) => {
+                let filepath = data_dir.join(relative_path);
+
+                File::open(&filepath)
+                    .and_then(|mut file| file.read_to_end(&mut buffer))
+                    .map_err(|err| PluckError::storage(filepath, err))?;
+            }
+            Self::Zip {
+                path,
+                zip,
+                data_prefix,
+            } => {
+                let mut zip = zip.lock().unwrap_or_else(|err| err.into_inner());
+
+                let mut file = zip
+                    .by_name(&format!("{}{}", data_prefix, relative_path))
+                    .map_err(|err| PluckError::storage(path.join(relative_path), err))?;
+
+                file.read_to_end(&mut buffer)
+                    .map_err(|err| PluckError::storage(path.join(relative_path), err))?;
+            }
+        }
+
+        Ok(buffer)
+    }
+
+    /// Reads the data file with the given name, with its JavaScript assignment
+    /// stripped off so that it can be parsed as JSON.
+    pub fn read_data_file(&self, file_name: &str) -> Result<String, PluckError> {
+        let contents = self.read(file_name)?;
+
+        let contents = String::from_utf8(contents).map_err(|err| {
+            PluckError::storage(
+                self.path().join(file_name),
+                std::io::Error::new(std::io::ErrorKind::InvalidData, err),
+            )
+        })?;
+
+        Ok(strip_data_prefix(&contents).to_string())
+    }
+}
+
+/// Strips the JavaScript assignment from the start of a data file, such as
+/// `window.YTD.tweets.part0 = ` or `window.YTD.direct_messages.part2 = `.
+pub fn strip_data_prefix(contents: &str) -> &str {
+    let trimmed = contents.trim_start();
+
+    match trimmed.strip_prefix("window.YTD.") {
+        Some(assignment) => assignment
+            .split_once('=')
+            .map(|(_, value)| value.trim_start())
+            .unwrap_or(trimmed),
+        None => trimmed,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+
+    use super::*;
+
+    /// Returns an empty directory for a test to write to.
+    fn empty_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("pluck-{}-{}", name, std::process::id()));
+
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    #[test]
+    fn data_files_are_discovered_in_order_of_their_parts() {
+        let dir = empty_dir("twitter-archive-dir");
+        let data_dir = dir.join(DATA_DIR_NAME);
+        std::fs::create_dir_all(&data_dir).unwrap();
+
+        for file_name in [
+            "tweets-part10.js",
+            "tweets-part2.js",
+            "tweets.js",
+            "tweets-part1.js",
+            "tweets-partial.js",
+            "tweetdeck.js",
+            "like.js",
+        ] {
+            std::fs::write(data_dir.join(file_name), "[]").unwrap();
+        }
+
+        // The archive can be opened from the directory it was extracted to or
+        // from its `data` directory.
+        for path in [&dir, &data_dir] {
+            let archive = TwitterArchive::open(path).unwrap();
+
+            assert_eq!(
+                archive.data_files("tweets").unwrap(),
+                vec![
+                    "tweets.js",
+                    "tweets-part1.js",
+                    "tweets-part2.js",
+                    "tweets-part10.js"
+                ]
+            );
+        }
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn zips_are_read_from_wherever_their_data_directory_is() {
+        let dir = empty_dir("twitter-archive-zip");
+        let zip_path = dir.join("twitter.zip");
+
+        let mut zip = ZipWriter::new(File::create(&zip_path).unwrap());
+        for (name, contents) in [
+            ("twitter-2024/Your archive.html", "<html></html>"),
+            (
+                "twitter-2024/data/tweets.js",
+                "window.YTD.tweets.part0 = []",
+            ),
+            ("twitter-2024/data/tweets_media/1-a.jpg", "photo"),
+        ] {
+            zip.start_file(name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+
+        let archive = TwitterArchive::open(&zip_path).unwrap();
+
+        assert_eq!(archive.data_files("tweets").unwrap(), vec!["tweets.js"]);
+        assert_eq!(archive.file_names("tweets_media").unwrap(), vec!["1-a.jpg"]);
+        assert_eq!(archive.read_data_file("tweets.js").unwrap(), "[]");
+        assert_eq!(archive.read("tweets_media/1-a.jpg").unwrap(), b"photo");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn any_data_file_assignment_is_stripped() {
+        assert_eq!(strip_data_prefix("window.YTD.tweets.part0 = [1]"), "[1]");
+        assert_eq!(
+            strip_data_prefix("\nwindow.YTD.direct_messages_group.part2 =[]"),
+            "[]"
+        );
+        assert_eq!(strip_data_prefix("[{\"a\": \"=\"}]"), "[{\"a\": \"=\"}]");
+    }
+}
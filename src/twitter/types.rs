@@ -0,0 +1,124 @@
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_with::{serde_as, DisplayFromStr};
+
+#[derive(Debug, Deserialize)]
+pub struct ArchivedLikeWrapper {
+    pub like: ArchivedLike,
+}
+
+#[serde_as]
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchivedLike {
+    #[serde_as(as = "DisplayFromStr")]
+    pub tweet_id: u64,
+
+    pub full_text: Option<String>,
+    pub expanded_url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchivedDmConversationWrapper {
+    pub dm_conversation: ArchivedDmConversation,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchivedDmConversation {
+    pub conversation_id: String,
+    pub messages: Vec<ArchivedDmEvent>,
+}
+
+/// An event in a DM conversation.
+///
+/// Only messages are archived, so other events (like people joining a group
+/// conversation) are skipped.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchivedDmEvent {
+    pub message_create: Option<ArchivedDirectMessage>,
+}
+
+#[serde_as]
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchivedDirectMessage {
+    #[serde_as(as = "DisplayFromStr")]
+    pub id: u64,
+
+    #[serde_as(as = "DisplayFromStr")]
+    pub sender_id: u64,
+
+    /// The recipient of the message, which is absent for group conversations.
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[serde(default)]
+    pub recipient_id: Option<u64>,
+
+    pub text: String,
+    pub created_at: DateTime<Utc>,
+
+    #[serde(default)]
+    pub media_urls: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ArchivedFollowerWrapper {
+    pub follower: ArchivedConnection,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ArchivedFollowingWrapper {
+    pub following: ArchivedConnection,
+}
+
+/// An account that follows, or is followed by, the owner of the archive.
+#[serde_as]
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchivedConnection {
+    #[serde_as(as = "DisplayFromStr")]
+    pub account_id: u64,
+
+    pub user_link: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ArchivedAccountWrapper {
+    pub account: ArchivedAccount,
+}
+
+#[serde_as]
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchivedAccount {
+    #[serde_as(as = "DisplayFromStr")]
+    pub account_id: u64,
+
+    pub username: String,
+    pub account_display_name: String,
+    pub email: Option<String>,
+    pub created_via: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ArchivedProfileWrapper {
+    pub profile: ArchivedProfile,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchivedProfile {
+    pub description: ArchivedProfileDescription,
+    pub avatar_media_url: Option<String>,
+    pub header_media_url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ArchivedProfileDescription {
+    pub bio: Option<String>,
+    pub website: Option<String>,
+    pub location: Option<String>,
+}